@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use nalgebra::{Matrix4, Vector4};
+use rayon::ThreadPoolBuilder;
+use simple_raster::mesh::{Face, Mesh, Vertex};
+use simple_raster::renderer::rasterizer::texture2d::Texture2D;
+use simple_raster::renderer::rasterizer::{RasterOptions, Rasterizer};
+use simple_raster::shader::BasicShader;
+
+const WIDTH: usize = 1280;
+const HEIGHT: usize = 720;
+const COLUMNS: usize = 320;
+const ROWS: usize = 180;
+
+// A couple of pixels' triangle in each cell of a grid over the screen, in clip space
+fn tiny_triangles() -> Mesh {
+    let (cell_width, cell_height) = (2.0 / COLUMNS as f32, 2.0 / ROWS as f32);
+    let vertex = |x: f32, y: f32| Vertex::from_pos(Vector4::new(x, y, 0.0, 1.0));
+
+    let faces = (0..ROWS).flat_map(|row| (0..COLUMNS).map(move |column| (row, column)))
+        .map(|(row, column)| {
+            let (x, y) = (-1.0 + column as f32 * cell_width, -1.0 + row as f32 * cell_height);
+            Face::new([vertex(x, y), vertex(x + cell_width / 2.0, y), vertex(x, y + cell_height / 2.0)])
+        })
+        .collect();
+    Mesh::new(None, faces)
+}
+
+fn rasterizer(threads: usize) -> Rasterizer {
+    let thread_pool = ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    let options = RasterOptions { thread_pool: Some(Arc::new(thread_pool)), ..Default::default() };
+
+    let mut rasterizer = Rasterizer::new(WIDTH, HEIGHT, options);
+    rasterizer.storage_mut().set_mat4s(vec![Matrix4::identity(), Matrix4::identity()]);
+    rasterizer.storage_mut().set_texture2ds(vec![Texture2D::from_rgba(1, 1, vec![255; 4]).unwrap()]);
+    rasterizer.storage_mut().set_texture2d_indices(&[0]);
+    rasterizer
+}
+
+// Each thread draws one band of rows. Binned, a thread only sets up the triangles over its own
+// band, so the total setup stays the same as the thread count, and with it the band count, grows.
+// Without binning every thread visited every triangle, sixteen times the setup at 16 threads.
+fn draw_binned(c: &mut Criterion) {
+    let mesh = tiny_triangles();
+
+    let mut group = c.benchmark_group("binning");
+    for threads in [1, 4, 16] {
+        let mut rasterizer = rasterizer(threads);
+
+        group.bench_function(BenchmarkId::new("tiny_triangles", threads), |b| b.iter(|| {
+            rasterizer.clear();
+            rasterizer.draw_mesh(&mesh, &BasicShader).unwrap();
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, draw_binned);
+criterion_main!(benches);
@@ -0,0 +1,81 @@
+use std::fs::File;
+use std::io::BufReader;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nalgebra::{Matrix4, Point3, Vector3};
+use simple_raster::camera::PerspectiveCamera;
+use simple_raster::mesh::{Mesh, ObjLoader};
+use simple_raster::renderer::rasterizer::texture2d::Texture2D;
+use simple_raster::renderer::rasterizer::{RasterOptions, Rasterizer};
+use simple_raster::shader::BasicShader;
+
+const WIDTH: usize = 1280;
+const HEIGHT: usize = 720;
+
+// The demo's model when run from the directory holding it, otherwise a sphere of a similar
+// triangle count so the bench still runs
+fn head_model() -> Mesh {
+    match File::open("african_head.obj") {
+        Ok(file) => ObjLoader::new().parse(BufReader::new(file)).swap_remove(0),
+        Err(_) => Mesh::uv_sphere(64, 40),
+    }
+}
+
+// Bound the way the demo binds it, framed by the demo's camera
+fn rasterizer(collect_stats: bool) -> Rasterizer {
+    let camera = PerspectiveCamera::new(
+        Point3::new(0.0, 0.0, 4.0),
+        Vector3::zeros(),
+        60.0_f32.to_radians(),
+        WIDTH as f32 / HEIGHT as f32,
+        0.1,
+        100.0,
+    );
+
+    let mut rasterizer = Rasterizer::new(WIDTH, HEIGHT, RasterOptions { collect_stats, ..Default::default() });
+    rasterizer.storage_mut().set_mat4s(vec![camera.view_projection, Matrix4::identity()]);
+    rasterizer.storage_mut().set_texture2ds(vec![Texture2D::from_rgba(1, 1, vec![255; 4]).unwrap()]);
+    rasterizer.storage_mut().set_texture2d_indices(&[0]);
+    rasterizer
+}
+
+fn draw_head(c: &mut Criterion) {
+    let mesh = head_model();
+    let indexed = mesh.to_indexed();
+
+    let mut group = c.benchmark_group("head_model");
+    for collect_stats in [false, true] {
+        let mut rasterizer = rasterizer(collect_stats);
+
+        group.bench_function(BenchmarkId::new("draw_mesh", collect_stats), |b| b.iter(|| {
+            rasterizer.clear();
+            rasterizer.draw_mesh(&mesh, &BasicShader).unwrap();
+        }));
+        group.bench_function(BenchmarkId::new("draw_indexed_mesh", collect_stats), |b| b.iter(|| {
+            rasterizer.clear();
+            rasterizer.draw_indexed_mesh(&indexed, &BasicShader).unwrap();
+        }));
+    }
+    group.finish();
+}
+
+// Reported in fragments per second, counted by a first draw with stats on and timed without them
+fn fragment_throughput(c: &mut Criterion) {
+    let indexed = head_model().to_indexed();
+
+    let mut counting = rasterizer(true);
+    counting.draw_indexed_mesh(&indexed, &BasicShader).unwrap();
+    let fragments = counting.stats().fragments_shaded;
+
+    let mut group = c.benchmark_group("head_model_fragments");
+    group.throughput(Throughput::Elements(fragments));
+
+    let mut rasterizer = rasterizer(false);
+    group.bench_function("draw_indexed_mesh", |b| b.iter(|| {
+        rasterizer.clear();
+        rasterizer.draw_indexed_mesh(&indexed, &BasicShader).unwrap();
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, draw_head, fragment_throughput);
+criterion_main!(benches);
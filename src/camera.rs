@@ -0,0 +1,240 @@
+use nalgebra::{Matrix4, Point3, Rotation3, Translation3, Vector3, Vector4};
+use crate::mesh::Mesh;
+
+pub struct PerspectiveCamera {
+    pub position: Point3<f32>,
+    pub rotation: Vector3<f32>,
+    pub view: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+    pub view_projection: Matrix4<f32>,
+}
+
+impl PerspectiveCamera {
+    pub fn new(position: Point3<f32>, rotation: Vector3<f32>, fov: f32, aspect: f32, z_near: f32, z_far: f32) -> Self {
+        let mut camera = Self {
+            position,
+            rotation,
+            view: Matrix4::identity(),
+            projection: Self::perspective_projection(fov, aspect, z_near, z_far),
+            view_projection: Matrix4::identity(),
+        };
+        camera.update_view();
+        camera
+    }
+
+    pub fn perspective_projection(fovy: f32, aspect: f32, z_near: f32, z_far: f32) -> Matrix4<f32> {
+        let m11 = 1.0 / (aspect * (fovy/2.0).tan());
+        let m22 = 1.0 / (fovy/2.0).tan();
+        let m33 = -(z_far + z_near) / (z_far - z_near);
+        let m34 = -(2.0 * z_far * z_near) / (z_far - z_near);
+
+        Matrix4::new(
+            m11, 0.0, 0.0, 0.0,
+            0.0, m22, 0.0, 0.0,
+            0.0, 0.0, m33, m34,
+            0.0, 0.0, -1.0, 0.0,
+        )
+    }
+
+    // Maps near to depth 1 and far to 0 after the divide, for use with DepthMode::Reversed.
+    // Geometry past the far plane is rejected by the depth test rather than clipped.
+    pub fn reversed_z_projection(fovy: f32, aspect: f32, z_near: f32, z_far: f32) -> Matrix4<f32> {
+        let m11 = 1.0 / (aspect * (fovy/2.0).tan());
+        let m22 = 1.0 / (fovy/2.0).tan();
+        let m33 = z_near / (z_far - z_near);
+        let m34 = (z_far * z_near) / (z_far - z_near);
+
+        Matrix4::new(
+            m11, 0.0, 0.0, 0.0,
+            0.0, m22, 0.0, 0.0,
+            0.0, 0.0, m33, m34,
+            0.0, 0.0, -1.0, 0.0,
+        )
+    }
+
+    pub fn update_view(&mut self) {
+        let roll = Rotation3::from_axis_angle(&Vector3::z_axis(), self.rotation.z);
+        let pitch = Rotation3::from_axis_angle(&Vector3::x_axis(), self.rotation.x);
+        let yaw = Rotation3::from_axis_angle(&Vector3::y_axis(), self.rotation.y);
+
+
+        let rotate = roll * pitch * yaw;
+
+        let translate = Translation3::from(-self.position);
+
+        self.view = Matrix4::from(rotate) * Matrix4::from(translate);
+        self.view_projection = self.projection * self.view
+    }
+
+    pub fn look_at(&mut self, position: Point3<f32>, target: Point3<f32>, up: Vector3<f32>) {
+        self.position = position;
+        self.view = Matrix4::look_at_rh(&position, &target, &up);
+        self.view_projection = self.projection * self.view;
+
+        // Keep the Euler angles in sync so a later update_view continues from this orientation,
+        // this is exact when up is +y
+        let direction = (target - position).normalize();
+        self.rotation = Vector3::new(-direction.y.asin(), direction.x.atan2(-direction.z), 0.0);
+    }
+
+    // Moves around target on a sphere of the given radius, continuing from the camera's current
+    // angle around it, and looks at it. Pitch is kept just short of the poles where look_at's
+    // +y up would flip the view.
+    pub fn orbit(&mut self, target: Point3<f32>, yaw_delta: f32, pitch_delta: f32, radius: f32) {
+        let max_pitch = std::f32::consts::FRAC_PI_2 - 0.01;
+
+        let offset = (self.position - target).try_normalize(f32::EPSILON).unwrap_or(Vector3::z());
+        let yaw = offset.x.atan2(offset.z) + yaw_delta;
+        let pitch = (offset.y.clamp(-1.0, 1.0).asin() + pitch_delta).clamp(-max_pitch, max_pitch);
+
+        let offset = Vector3::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos());
+        self.look_at(target + offset * radius, target, Vector3::y());
+    }
+
+    // Moves back from the framing's centre against direction and looks at it
+    pub fn frame(&mut self, framing: &Framing, direction: Vector3<f32>) {
+        self.look_at(framing.position(direction), framing.centre, Vector3::y());
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_projection(&self.view_projection)
+    }
+}
+
+// Where a camera has to be to fit a set of meshes in view, from the sphere around their
+// combined bounding box so it fits looking from any direction
+#[derive(Copy, Clone, Debug)]
+pub struct Framing {
+    pub centre: Point3<f32>,
+    pub radius: f32,
+    // From the centre, the nearest the camera can be with the sphere inside both the vertical and horizontal fov
+    pub distance: f32,
+}
+
+impl Framing {
+    // Meshes with no faces are skipped, with none at all the framing is a point at the origin
+    pub fn new(meshes: &[Mesh], fovy: f32, aspect: f32) -> Self {
+        let (min, max) = meshes.iter()
+            .filter(|mesh| !mesh.faces.is_empty())
+            .map(Mesh::bounding_box)
+            .reduce(|(min, max), (mesh_min, mesh_max)| (min.inf(&mesh_min), max.sup(&mesh_max)))
+            .unwrap_or((Vector3::zeros(), Vector3::zeros()));
+
+        let centre = Point3::from((min + max) / 2.0);
+        let radius = (max - min).norm() / 2.0;
+
+        let fovx = 2.0 * (aspect * (fovy / 2.0).tan()).atan();
+        let distance = radius / (fovy.min(fovx) / 2.0).sin();
+
+        Self { centre, radius, distance }
+    }
+
+    // Looking along direction, so -z puts the camera on the +z side as the default view does
+    pub fn position(&self, direction: Vector3<f32>) -> Point3<f32> {
+        let direction = direction.try_normalize(f32::EPSILON).unwrap_or(-Vector3::z());
+        self.centre - direction * self.distance
+    }
+}
+
+// The six planes bounding what a view projection can see, in the space the matrix is applied
+// to, world space for a camera's view_projection. Takes depth as Standard's -w to w.
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    // Left, right, bottom, top, near and far, each (a, b, c, d) with a * x + b * y + c * z + d
+    // the signed distance to the plane, normalized so (a, b, c) has unit length, negative outside
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Gribb-Hartmann extraction, each plane is the w row plus or minus the x, y or z row
+    pub fn from_view_projection(view_projection: &Matrix4<f32>) -> Self {
+        let row = |index: usize| view_projection.row(index).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let planes = [w + x, w - x, w + y, w - y, w + z, w - z].map(|plane| {
+            let length = plane.xyz().norm();
+            if length > f32::EPSILON { plane / length } else { plane }
+        });
+
+        Self { planes }
+    }
+
+    pub fn contains_point(&self, point: &Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| Self::distance(plane, &point.coords) >= 0.0)
+    }
+
+    // Conservative, a box outside the frustum but not wholly behind any one plane, as near its
+    // corners can be, still counts as intersecting
+    pub fn intersects_aabb(&self, (min, max): (Vector3<f32>, Vector3<f32>)) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal, if it's outside the whole box is
+            let corner = Vector3::new(
+                if plane.x >= 0.0 { max.x } else { min.x },
+                if plane.y >= 0.0 { max.y } else { min.y },
+                if plane.z >= 0.0 { max.z } else { min.z },
+            );
+            Self::distance(plane, &corner) >= 0.0
+        })
+    }
+
+    fn distance(plane: &Vector4<f32>, point: &Vector3<f32>) -> f32 {
+        plane.xyz().dot(point) + plane.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+    use nalgebra::{Matrix4, Point3, Vector3};
+    use crate::mesh::Mesh;
+    use super::{Framing, Frustum, PerspectiveCamera};
+
+    #[test]
+    fn framing_fits_a_mesh_of_known_extent() {
+        // A 2 unit cube spanning 1 to 3, 0 to 2 and -1 to 1
+        let mut cube = Mesh::cube();
+        cube.transform(&(Matrix4::new_translation(&Vector3::new(2.0, 1.0, 0.0)) * Matrix4::new_scaling(2.0)));
+
+        // Wider than tall, so the vertical fov decides the distance
+        let framing = Framing::new(&[cube], FRAC_PI_2, 2.0);
+        assert_eq!(framing.centre, Point3::new(2.0, 1.0, 0.0));
+        assert!((framing.radius - 3.0_f32.sqrt()).abs() < 1e-5);
+        assert!((framing.distance - 6.0_f32.sqrt()).abs() < 1e-5);
+
+        let mut camera = PerspectiveCamera::new(Point3::origin(), Vector3::zeros(), FRAC_PI_2, 2.0, 0.1, 100.0);
+        camera.frame(&framing, -Vector3::z());
+        assert!((camera.position - Point3::new(2.0, 1.0, 6.0_f32.sqrt())).norm() < 1e-5);
+
+        for corner in 0..8 {
+            let corner = Vector3::new(1.0 + 2.0 * (corner & 1) as f32, 2.0 * (corner >> 1 & 1) as f32, -1.0 + 2.0 * (corner >> 2) as f32);
+            let clip = camera.view_projection * corner.push(1.0);
+            assert!(clip.x.abs() <= clip.w && clip.y.abs() <= clip.w, "{corner:?} is outside the view");
+        }
+    }
+
+    // The default camera's frustum, looking down -z from the origin with near at 0.1 and far at 100
+    fn frustum() -> Frustum {
+        Frustum::from_view_projection(&PerspectiveCamera::perspective_projection(FRAC_PI_2, 1.0, 0.1, 100.0))
+    }
+
+    #[test]
+    fn frustum_contains_only_points_between_the_near_and_far_planes() {
+        let frustum = frustum();
+
+        assert!(frustum.contains_point(&Point3::new(0.5, -0.5, -5.0)));
+        assert!(!frustum.contains_point(&Point3::new(0.0, 0.0, -0.05)));
+        assert!(!frustum.contains_point(&Point3::new(0.0, 0.0, 1.0)));
+        assert!(!frustum.contains_point(&Point3::new(0.0, 0.0, -150.0)));
+    }
+
+    #[test]
+    fn frustum_rejects_only_boxes_wholly_outside_a_plane() {
+        let frustum = frustum();
+
+        // Between z = -6 and -4 the view reaches at most 6 along x
+        assert!(!frustum.intersects_aabb((Vector3::new(7.0, -1.0, -6.0), Vector3::new(9.0, 1.0, -4.0))));
+        // Straddling the right plane, then the far plane
+        assert!(frustum.intersects_aabb((Vector3::new(4.0, -1.0, -6.0), Vector3::new(9.0, 1.0, -4.0))));
+        assert!(frustum.intersects_aabb((Vector3::new(-1.0, -1.0, -120.0), Vector3::new(1.0, 1.0, -90.0))));
+    }
+}
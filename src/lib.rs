@@ -0,0 +1,6 @@
+pub mod camera;
+pub mod material;
+pub mod mesh;
+pub mod primitives;
+pub mod shader;
+pub mod renderer;
@@ -1,74 +1,18 @@
-use crate::mesh::{Face, Mesh, ObjLoader, Vertex};
-use crate::shader::BasicShader;
+use simple_raster::mesh::{Mesh, ObjLoader};
+use simple_raster::shader::BasicShader;
 use minifb::Key;
-use nalgebra::{Matrix4, Point3, Rotation3, Translation3, Vector3, Vector4};
+use nalgebra::{Matrix4, Point3, Rotation3, Translation3, Vector3};
+use simple_raster::camera::PerspectiveCamera;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::Path;
 use std::time::Instant;
-use crate::renderer::post_processor::PostProcessorOptions;
-use crate::renderer::rasterizer::RasterOptions;
-use crate::renderer::rasterizer::texture2d::Texture2D;
-use crate::renderer::{Renderer, RendererOptions};
-
-mod mesh;
-mod shader;
-mod renderer;
-
-fn load_texture(path: impl AsRef<Path>) -> Option<image::RgbaImage> {
-    let img = image::open(path).ok()?;
-    Some(img.to_rgba8())
-}
-
-struct PerspectiveCamera {
-    position: Point3<f32>,
-    rotation: Vector3<f32>,
-    view: Matrix4<f32>,
-    projection: Matrix4<f32>,
-    view_projection: Matrix4<f32>,
-}
-
-impl PerspectiveCamera {
-    fn new(position: Point3<f32>, rotation: Vector3<f32>, fov: f32, aspect: f32, z_near: f32, z_far: f32) -> Self {
-        let mut camera = Self {
-            position,
-            rotation,
-            view: Matrix4::identity(),
-            projection: Self::perspective_projection(fov, aspect, z_near, z_far),
-            view_projection: Matrix4::identity(),
-        };
-        camera.update_view();
-        camera
-    }
-
-    fn perspective_projection(fovy: f32, aspect: f32, z_near: f32, z_far: f32) -> Matrix4<f32> {
-        let m11 = 1.0 / (aspect * (fovy/2.0).tan());
-        let m22 = 1.0 / (fovy/2.0).tan();
-        let m33 = -(z_far + z_near) / (z_far - z_near);
-        let m34 = -(2.0 * z_far * z_near) / (z_far - z_near);
-
-        Matrix4::new(
-            m11, 0.0, 0.0, 0.0,
-            0.0, m22, 0.0, 0.0,
-            0.0, 0.0, m33, m34,
-            0.0, 0.0, -1.0, 0.0,
-        )
-    }
-
-    fn update_view(&mut self) {
-        let roll = Rotation3::from_axis_angle(&Vector3::z_axis(), self.rotation.z);
-        let pitch = Rotation3::from_axis_angle(&Vector3::x_axis(), self.rotation.x);
-        let yaw = Rotation3::from_axis_angle(&Vector3::y_axis(), self.rotation.y);
-
-
-        let rotate = roll * pitch * yaw;
-
-        let translate = Translation3::from(-self.position);
-
-        self.view = Matrix4::from(rotate) * Matrix4::from(translate);
-        self.view_projection = self.projection * self.view
-    }
-}
+use simple_raster::renderer::post_processor::PostProcessorOptions;
+use simple_raster::renderer::rasterizer::background::Background;
+use simple_raster::renderer::rasterizer::depth::DepthMode;
+use simple_raster::renderer::rasterizer::{PolygonMode, RasterOptions, TransparencyMode};
+use simple_raster::renderer::rasterizer::texture2d::Texture2D;
+use simple_raster::renderer::scene::{DrawItem, Scene, Transparency};
+use simple_raster::renderer::{Renderer, RendererOptions};
 
 fn main() {
     const WIDTH: usize = 1280;
@@ -77,32 +21,47 @@ fn main() {
     let mut mesh_loader = ObjLoader::new();
     let file = File::open("african_head.obj").unwrap();
     let meshes = mesh_loader.parse(BufReader::new(file));
-    let mesh = &meshes[0];
-
-    let mesh2 = Mesh::new(None, vec![
-        Face::new([
-            Vertex::from_pos_tex(Vector4::new(0.0, 0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, 0.0)),  // Bottom-left, tex(0.0, 0.0)
-            Vertex::from_pos_tex(Vector4::new(1.0, 0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0)),  // Bottom-right, tex(1.0, 0.0)
-            Vertex::from_pos_tex(Vector4::new(1.0, 1.0, 0.0, 1.0), Vector3::new(1.0, 1.0, 0.0)),  // Top-right, tex(1.0, 1.0)
-        ]),
-        Face::new([
-            Vertex::from_pos_tex(Vector4::new(0.0, 0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, 0.0)),  // Bottom-left, tex(0.0, 0.0)
-            Vertex::from_pos_tex(Vector4::new(1.0, 1.0, 0.0, 1.0), Vector3::new(1.0, 1.0, 0.0)),  // Top-right, tex(1.0, 1.0)
-            Vertex::from_pos_tex(Vector4::new(0.0, 1.0, 0.0, 1.0), Vector3::new(0.0, 1.0, 0.0)),  // Top-left, tex(0.0, 1.0)
-        ]),
-    ]);
+    let mesh = meshes[0].to_indexed();
+
+    let mesh2 = Mesh::quad();
 
-    let texture: Texture2D = load_texture("african_head_diffuse.tga").unwrap().into();
-    let texture2: Texture2D = load_texture("blending_transparent_window.png").unwrap().into();
+    let texture = Texture2D::load("african_head_diffuse.tga").unwrap();
+    let texture2 = Texture2D::load("blending_transparent_window.png").unwrap();
 
     let render_options = RendererOptions {
         raster_options: RasterOptions {
             cull_backfaces: false,
-            background_colour: Vector3::new(0.529, 0.808, 0.980),
+            polygon_mode: PolygonMode::Fill,
+            background: Background::Solid(Vector3::new(0.529, 0.808, 0.980)),
+            stencil: None,
+            alpha_cutoff: None,
+            transparency: TransparencyMode::Sorted,
+            fog: None,
+            depth_mode: DepthMode::Standard,
+            sort_front_to_back: false,
+            conservative: false,
+            reject_outside_depth_range: true,
+            depth_bias: 0.0,
+            slope_scaled_depth_bias: 0.0,
+            render_targets: 0,
+            exposure: 1.0,
+            output_srgb: false,
+            perspective_correct: true,
+            min_triangle_area: None,
+            max_fragments_per_pixel: None,
+            thread_pool: None,
+            collect_stats: false,
         },
         post_processor_options: PostProcessorOptions {
             fxaa: true,
-        }
+            sharpen: None,
+            color_filter: None,
+            chromatic_aberration: None,
+            outline: None,
+            debug_depth: false,
+        },
+        render_scale: 1.0,
+        supersample: 1,
     };
     let mut renderer = Renderer::new(WIDTH, HEIGHT, render_options);
     
@@ -123,7 +82,10 @@ fn main() {
         far,
     );
 
+    // Spinning is paused, uncomment the increment in the loop to resume it
+    #[allow(unused_mut)]
     let mut model_rotation_angle = 0.0;
+    #[allow(unused_variables)]
     let model_rotation_speed = 0.01;
 
     renderer.rasterizer.storage_mut().set_texture2ds(vec![
@@ -133,21 +95,18 @@ fn main() {
 
     let shader = BasicShader;
 
-    let window_transform = Translation3::from(Vector3::new(0.0, 0.0, 1.0)).to_homogeneous();
+    let window_transform = Translation3::from(Vector3::new(0.5, 0.5, 1.0)).to_homogeneous();
     renderer.rasterizer.storage_mut().set_mat4s(vec![
         camera.view_projection,
         window_transform,
     ]);
-    renderer.rasterizer.storage_mut().set_texture2d_indices(vec![1]);
-    renderer.rasterizer.draw_mesh(&mesh2, &shader);
+    renderer.rasterizer.storage_mut().set_texture2d_indices(&[1]);
+    renderer.rasterizer.draw_mesh(&mesh2, &shader).unwrap();
 
     let mut model_transform = Matrix4::identity();
-    renderer.rasterizer.storage_mut().set_mat4s(vec![
-        camera.view_projection,
-        model_transform,
-    ]);
-    renderer.rasterizer.storage_mut().set_texture2d_indices(vec![0]);
-    renderer.rasterizer.draw_mesh(mesh, &shader);
+    renderer.rasterizer.storage_mut().set_mat4(1, model_transform);
+    renderer.rasterizer.storage_mut().set_texture2d_index(0, 0);
+    renderer.rasterizer.draw_indexed_mesh(&mesh, &shader).unwrap();
 
 
     renderer.render(&mut buffer);
@@ -161,7 +120,17 @@ fn main() {
     window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
     //window.set_target_fps(100);
     let mut now = Instant::now();
+    let (mut width, mut height) = (WIDTH, HEIGHT);
     while window.is_open() && !window.is_key_down(Key::Escape) {
+        let (new_width, new_height) = window.get_size();
+        if (new_width, new_height) != (width, height) && new_width > 0 && new_height > 0 {
+            width = new_width;
+            height = new_height;
+            renderer.resize(width, height);
+            buffer.resize(width * height, 0);
+            camera.projection = PerspectiveCamera::perspective_projection(fovy, width as f32 / height as f32, near, far);
+        }
+
         let movement_speed = 0.05;
         let rotation_speed = 0.02;
 
@@ -196,23 +165,13 @@ fn main() {
         let model_rotation = Rotation3::from_axis_angle(&Vector3::y_axis(), model_rotation_angle).to_homogeneous();
         model_transform = model_rotation;
 
-        renderer.rasterizer.storage_mut().set_mat4s(vec![
-            camera.view_projection,
-            window_transform,
-        ]);
-        renderer.rasterizer.storage_mut().set_texture2d_indices(vec![1]);
-        renderer.rasterizer.draw_mesh(&mesh2, &shader);
-
-        renderer.rasterizer.storage_mut().set_mat4s(vec![
-            camera.view_projection,
-            model_transform,
-        ]);
-        renderer.rasterizer.storage_mut().set_texture2d_indices(vec![0]);
-        renderer.rasterizer.draw_mesh(mesh, &shader);
-
+        let mut scene = Scene::new(camera.view_projection);
+        scene.sort = true;
+        scene.add(DrawItem::new(&mesh2, window_transform, vec![1], &shader).with_transparency(Transparency::Blend));
+        scene.add(DrawItem::new(&mesh, model_transform, vec![0], &shader));
 
-        renderer.render(&mut buffer);
-        window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
+        renderer.render_scene(&scene, &mut buffer).unwrap();
+        window.update_with_buffer(&buffer, width, height).unwrap();
         println!("{:?} fps", 1.0 / now.elapsed().as_secs_f64());
         now = Instant::now();
     }
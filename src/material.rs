@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+use image::ImageError;
+use nalgebra::Vector3;
+use crate::renderer::rasterizer::texture2d::Texture2D;
+
+#[derive(Copy, Clone)]
+pub struct Material {
+    pub base_colour: Vector3<f32>,
+    pub specular: Vector3<f32>,
+    pub shininess: f32,
+    pub opacity: f32,
+    // Slot in the storage's texture list, bound as texture 0 when the mesh is drawn
+    pub texture: Option<usize>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_colour: Vector3::new(1.0, 1.0, 1.0),
+            specular: Vector3::zeros(),
+            shininess: 32.0,
+            opacity: 1.0,
+            texture: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MaterialError {
+    Io { path: PathBuf, source: io::Error },
+    Texture { path: PathBuf, source: ImageError },
+}
+
+impl Display for MaterialError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaterialError::Io { path, source } => write!(f, "Failed to read {}: {source}", path.display()),
+            MaterialError::Texture { path, source } => write!(f, "Failed to load texture {}: {source}", path.display()),
+        }
+    }
+}
+
+impl Error for MaterialError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MaterialError::Io { source, .. } => Some(source),
+            MaterialError::Texture { source, .. } => Some(source),
+        }
+    }
+}
+
+// Materials by name, their texture slots index into textures
+#[derive(Default)]
+pub struct MaterialLibrary {
+    pub materials: HashMap<String, Material>,
+    pub textures: Vec<Texture2D>,
+}
+
+impl MaterialLibrary {
+    // Texture slots of the other library's materials are shifted past this library's textures
+    pub fn extend(&mut self, other: MaterialLibrary) {
+        let offset = self.textures.len();
+        self.textures.extend(other.textures);
+
+        for (name, mut material) in other.materials {
+            material.texture = material.texture.map(|texture| texture + offset);
+            self.materials.insert(name, material);
+        }
+    }
+}
+
+pub struct MtlMaterial {
+    pub name: String,
+    pub material: Material,
+    // As written in the file, relative to the .mtl
+    pub diffuse_map: Option<String>,
+}
+
+pub struct MtlLoader;
+
+impl MtlLoader {
+    // Loads the materials and the diffuse maps they reference, looked up relative to the file
+    pub fn load(path: impl AsRef<Path>) -> Result<MaterialLibrary, MaterialError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|source| MaterialError::Io { path: path.to_path_buf(), source })?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        let mut library = MaterialLibrary::default();
+        let mut loaded_maps: HashMap<String, usize> = HashMap::new();
+
+        for mtl_material in Self::parse(BufReader::new(file)) {
+            let mut material = mtl_material.material;
+
+            if let Some(diffuse_map) = mtl_material.diffuse_map {
+                let texture = match loaded_maps.get(&diffuse_map) {
+                    Some(&texture) => texture,
+                    None => {
+                        let map_path = directory.join(&diffuse_map);
+                        let image = image::open(&map_path).map_err(|source| MaterialError::Texture { path: map_path, source })?;
+
+                        library.textures.push(image.to_rgba8().into());
+                        loaded_maps.insert(diffuse_map, library.textures.len() - 1);
+                        library.textures.len() - 1
+                    }
+                };
+                material.texture = Some(texture);
+            }
+
+            library.materials.insert(mtl_material.name, material);
+        }
+
+        Ok(library)
+    }
+
+    pub fn parse(reader: impl BufRead) -> Vec<MtlMaterial> {
+        let mut materials: Vec<MtlMaterial> = Vec::new();
+
+        for line in reader.lines() {
+            let Ok(line) = line else { panic!("Failed to read line: {line:?}") };
+            let mut words = line.split_whitespace();
+
+            let Some(line_prefix) = words.next() else { continue };
+
+            if line_prefix == "newmtl" {
+                materials.push(MtlMaterial {
+                    name: line.trim().trim_start_matches("newmtl").trim().to_string(),
+                    material: Material::default(),
+                    diffuse_map: None,
+                });
+                continue;
+            }
+
+            // Statements before the first newmtl have nothing to apply to
+            let Some(current) = materials.last_mut() else { continue };
+
+            match line_prefix {
+                "Kd" => if let Some(colour) = Self::parse_colour(words) { current.material.base_colour = colour },
+                "Ks" => if let Some(colour) = Self::parse_colour(words) { current.material.specular = colour },
+                "Ns" => if let Some(shininess) = Self::parse_f32(words) { current.material.shininess = shininess },
+                "d" => if let Some(opacity) = Self::parse_f32(words) { current.material.opacity = opacity },
+                // Options before the file name aren't supported, the file name is taken as the last word
+                "map_Kd" => current.diffuse_map = words.last().map(str::to_string),
+                _ => {
+                    // Unsupported statements are skipped
+                },
+            }
+        }
+
+        materials
+    }
+
+    fn parse_f32(mut words: SplitWhitespace) -> Option<f32> {
+        words.next()?.parse().ok()
+    }
+
+    fn parse_colour(mut words: SplitWhitespace) -> Option<Vector3<f32>> {
+        let r = words.next()?.parse().ok()?;
+        // A single value is used for all three channels
+        let g = words.next().and_then(|g| g.parse().ok()).unwrap_or(r);
+        let b = words.next().and_then(|b| b.parse().ok()).unwrap_or(r);
+
+        Some(Vector3::new(r, g, b))
+    }
+}
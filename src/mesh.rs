@@ -1,10 +1,18 @@
-use std::io::BufRead;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use std::str::SplitWhitespace;
-use nalgebra::{Vector3, Vector4};
+use nalgebra::{Matrix4, Vector3, Vector4};
+use crate::material::{Material, MaterialError, MaterialLibrary, MtlLoader};
+use crate::renderer::rasterizer::texture2d::Texture2D;
 
 pub struct Mesh {
     pub name: Option<String>,
     pub faces: Vec<Face>,
+    pub material: Material,
 }
 
 impl Mesh {
@@ -12,8 +20,247 @@ impl Mesh {
         Self {
             name,
             faces,
+            material: Material::default(),
         }
     }
+
+    // Has the default material, the meshes' own are dropped
+    pub fn concat(meshes: &[Mesh]) -> Self {
+        let mut merged = Self::new(None, Vec::with_capacity(meshes.iter().map(|mesh| mesh.faces.len()).sum()));
+        for mesh in meshes {
+            merged.merge(mesh);
+        }
+        merged
+    }
+
+    pub fn merge(&mut self, other: &Mesh) {
+        // The merged mesh keeps its own name and material, only picking up a name if it had none.
+        // The other mesh's material is dropped, merge meshes that share one or draw them apart.
+        if self.name.is_none() {
+            self.name.clone_from(&other.name);
+        }
+        self.faces.extend_from_slice(&other.faces);
+    }
+
+    pub fn transform(&mut self, matrix: &Matrix4<f32>) {
+        // Positions go through the full matrix so w is preserved, directions only the linear part.
+        // Normals use the inverse transpose so they stay perpendicular under non-uniform scale.
+        let linear = matrix.fixed_view::<3, 3>(0, 0).into_owned();
+        let normal_matrix = linear.try_inverse().map(|inverse| inverse.transpose()).unwrap_or(linear);
+
+        for vertex in self.faces.iter_mut().flat_map(|face| &mut face.vertices) {
+            vertex.position = matrix * vertex.position;
+            vertex.normals = (normal_matrix * vertex.normals).try_normalize(f32::EPSILON).unwrap_or(vertex.normals);
+
+            let tangent = (linear * vertex.tangent.xyz()).try_normalize(f32::EPSILON).unwrap_or(vertex.tangent.xyz());
+            vertex.tangent = tangent.push(vertex.tangent.w);
+        }
+    }
+
+    pub fn bounding_box(&self) -> (Vector3<f32>, Vector3<f32>) {
+        bounding_box(self.faces.iter().flat_map(|face| &face.vertices))
+    }
+
+    // Centre of the surface, each face weighted by its area so densely tessellated regions don't
+    // pull it over. Falls back to the mean vertex position when every face is degenerate.
+    // Faces and vertices at infinity are left out.
+    pub fn centroid(&self) -> Vector3<f32> {
+        let finite_faces = || self.faces.iter().filter(|face| !face.vertices.iter().any(Vertex::is_at_infinity));
+
+        let (weighted_sum, total_area) = finite_faces()
+            .map(|face| {
+                let [a, b, c] = face.vertices.map(|vertex| vertex.point());
+                let area = (b - a).cross(&(c - a)).norm() / 2.0;
+                ((a + b + c) / 3.0 * area, area)
+            })
+            .fold((Vector3::zeros(), 0.0), |(sum, total), (weighted, area)| (sum + weighted, total + area));
+
+        if total_area > 0.0 {
+            return weighted_sum / total_area;
+        }
+
+        let points = self.faces.iter()
+            .flat_map(|face| &face.vertices)
+            .filter(|vertex| !vertex.is_at_infinity())
+            .map(Vertex::point)
+            .collect::<Vec<_>>();
+        if points.is_empty() { return Vector3::zeros() }
+
+        points.iter().sum::<Vector3<f32>>() / points.len() as f32
+    }
+
+    pub fn to_indexed(&self) -> IndexedMesh {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::with_capacity(self.faces.len());
+        let mut vertex_lookup = HashMap::new();
+
+        for face in &self.faces {
+            let face_indices = face.vertices.map(|vertex| {
+                *vertex_lookup.entry(vertex.bits()).or_insert_with(|| {
+                    vertices.push(vertex);
+                    (vertices.len() - 1) as u32
+                })
+            });
+            indices.push(face_indices);
+        }
+
+        IndexedMesh {
+            name: self.name.clone(),
+            vertices,
+            indices,
+            material: self.material,
+        }
+    }
+
+    pub fn compute_smooth_normals(&mut self) {
+        // Faces don't share vertices, so vertices are matched up by position.
+        // Summing unnormalized face normals weights each face by its area.
+        let mut normals: HashMap<[u32; 3], Vector3<f32>> = HashMap::new();
+
+        for face in &self.faces {
+            let face_normal = face.geometric_normal();
+            for vertex in &face.vertices {
+                *normals.entry(vertex.position_bits()).or_insert_with(Vector3::zeros) += face_normal;
+            }
+        }
+
+        for face in &mut self.faces {
+            for vertex in &mut face.vertices {
+                let normal = normals[&vertex.position_bits()];
+                vertex.normals = normal.try_normalize(f32::EPSILON).unwrap_or(vertex.normals);
+            }
+        }
+    }
+
+    pub fn compute_flat_normals(&mut self) {
+        for face in &mut self.faces {
+            // Degenerate faces keep their existing normals rather than becoming NaN
+            let Some(normal) = face.geometric_normal().try_normalize(f32::EPSILON) else { continue };
+
+            for vertex in &mut face.vertices {
+                vertex.normals = normal;
+            }
+        }
+    }
+
+    pub fn compute_tangents(&mut self) {
+        let mut bases: HashMap<Vec<u32>, (Vector3<f32>, Vector3<f32>)> = HashMap::new();
+
+        for face in &self.faces {
+            let Some((tangent, bitangent)) = face.tangent_basis() else { continue };
+
+            for vertex in &face.vertices {
+                let basis = bases.entry(vertex.surface_bits()).or_insert((Vector3::zeros(), Vector3::zeros()));
+                basis.0 += tangent;
+                basis.1 += bitangent;
+            }
+        }
+
+        for face in &mut self.faces {
+            for vertex in &mut face.vertices {
+                let Some(&(tangent, bitangent)) = bases.get(&vertex.surface_bits()) else { continue };
+
+                // Gram-Schmidt the tangent against the normal, mirrored UVs flip the handedness
+                let normal = vertex.normals;
+                let Some(tangent) = (tangent - normal * normal.dot(&tangent)).try_normalize(f32::EPSILON) else { continue };
+                let handedness = if normal.cross(&tangent).dot(&bitangent) < 0.0 { -1.0 } else { 1.0 };
+
+                vertex.tangent = tangent.push(handedness);
+            }
+        }
+    }
+}
+
+pub struct IndexedMesh {
+    pub name: Option<String>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<[u32; 3]>,
+    pub material: Material,
+}
+
+impl IndexedMesh {
+    pub fn new(name: Option<String>, vertices: Vec<Vertex>, indices: Vec<[u32; 3]>) -> Self {
+        Self {
+            name,
+            vertices,
+            indices,
+            material: Material::default(),
+        }
+    }
+
+    pub fn bounding_box(&self) -> (Vector3<f32>, Vector3<f32>) {
+        bounding_box(&self.vertices)
+    }
+
+    pub fn merge(&mut self, other: &IndexedMesh) {
+        if self.name.is_none() {
+            self.name.clone_from(&other.name);
+        }
+
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices.extend(other.indices.iter().map(|face| face.map(|index| index + offset)));
+    }
+}
+
+// Min and max corners of the vertex positions, zeros when there are none. Vertices at infinity
+// can't be bounded and are skipped.
+fn bounding_box<'a>(vertices: impl IntoIterator<Item = &'a Vertex>) -> (Vector3<f32>, Vector3<f32>) {
+    let mut positions = vertices.into_iter()
+        .filter(|vertex| !vertex.is_at_infinity())
+        .map(Vertex::point);
+
+    let Some(first) = positions.next() else { return (Vector3::zeros(), Vector3::zeros()) };
+
+    positions.fold((first, first), |(min, max), position| (min.inf(&position), max.sup(&position)))
+}
+
+// Authored in quads and split into triangles when drawn, each quad's four vertices are only shaded once
+pub struct QuadMesh {
+    pub name: Option<String>,
+    pub quads: Vec<Quad>,
+    pub material: Material,
+}
+
+impl QuadMesh {
+    pub fn new(name: Option<String>, quads: Vec<Quad>) -> Self {
+        Self {
+            name,
+            quads,
+            material: Material::default(),
+        }
+    }
+
+    pub fn bounding_box(&self) -> (Vector3<f32>, Vector3<f32>) {
+        bounding_box(self.quads.iter().flat_map(|quad| &quad.vertices))
+    }
+
+    pub fn to_mesh(&self) -> Mesh {
+        let mut mesh = Mesh::new(self.name.clone(), self.quads.iter().flat_map(Quad::triangulate).collect());
+        mesh.material = self.material;
+        mesh
+    }
+}
+
+// Four vertices in winding order around a planar, convex quad
+#[derive(Default, Copy, Clone)]
+pub struct Quad {
+    pub vertices: [Vertex; 4],
+}
+
+impl Quad {
+    // Split along the diagonal from the first to the third vertex, keeping the winding
+    pub const TRIANGLES: [[usize; 3]; 2] = [[0, 1, 2], [0, 2, 3]];
+
+    pub fn new(vertices: [Vertex; 4]) -> Self {
+        Self {
+            vertices,
+        }
+    }
+
+    pub fn triangulate(&self) -> [Face; 2] {
+        Self::TRIANGLES.map(|indices| Face::new(indices.map(|index| self.vertices[index])))
+    }
 }
 
 #[derive(Default, Copy, Clone)]
@@ -27,13 +274,46 @@ impl Face {
             vertices,
         }
     }
+
+    // Counter-clockwise winding, length is twice the face's area
+    pub fn geometric_normal(&self) -> Vector3<f32> {
+        let [a, b, c] = self.vertices.map(|vertex| vertex.point());
+        (b - a).cross(&(c - a))
+    }
+
+    // Unnormalized tangent and bitangent from the UV gradients, None if the UVs are degenerate
+    pub fn tangent_basis(&self) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let [a, b, c] = self.vertices;
+
+        let edge1 = b.point() - a.point();
+        let edge2 = c.point() - a.point();
+        let delta_uv1 = b.texture_coords.xy() - a.texture_coords.xy();
+        let delta_uv2 = c.texture_coords.xy() - a.texture_coords.xy();
+
+        let determinant = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+        if determinant.abs() < f32::EPSILON { return None }
+
+        let r = 1.0 / determinant;
+        let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) * r;
+        let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) * r;
+
+        Some((tangent, bitangent))
+    }
 }
 
 #[derive(Default, Copy, Clone)]
 pub struct Vertex {
+    // Homogeneous, usually with a w of 1. Vertex shaders are given it with w divided out, so
+    // (2, 4, 6, 2) draws exactly as (1, 2, 3, 1) does, varyings and depth included. A w of 0 is
+    // passed as is, a point at infinity in that direction which translations don't move.
     pub position: Vector4<f32>,
     pub texture_coords: Vector3<f32>,
     pub normals: Vector3<f32>,
+    // w holds the bitangent handedness
+    pub tangent: Vector4<f32>,
+    // Up to four bones blended by weight, all zero weights leave the vertex unskinned
+    pub bone_indices: [u32; 4],
+    pub bone_weights: Vector4<f32>,
 }
 
 impl Vertex {
@@ -42,6 +322,9 @@ impl Vertex {
             position,
             texture_coords,
             normals: Vector3::new(0.0, 0.0, 1.0),
+            tangent: Vector4::new(1.0, 0.0, 0.0, 1.0),
+            bone_indices: [0; 4],
+            bone_weights: Vector4::zeros(),
         }
     }
 
@@ -50,46 +333,191 @@ impl Vertex {
             position,
             texture_coords: Vector3::new(0.0, 0.0, 1.0),
             normals: Vector3::new(0.0, 0.0, 1.0),
+            tangent: Vector4::new(1.0, 0.0, 0.0, 1.0),
+            bone_indices: [0; 4],
+            bone_weights: Vector4::zeros(),
         }
     }
+
+    pub fn from_pos_normal(position: Vector4<f32>, normal: Vector3<f32>) -> Self {
+        Self {
+            normals: normal,
+            ..Self::from_pos(position)
+        }
+    }
+
+    pub fn from_pos_tex_normal(position: Vector4<f32>, texture_coords: Vector3<f32>, normal: Vector3<f32>) -> Self {
+        Self {
+            normals: normal,
+            ..Self::from_pos_tex(position, texture_coords)
+        }
+    }
+
+    // The position scaled to a w of 1, or as it is at infinity
+    pub fn normalized_position(&self) -> Vector4<f32> {
+        if self.is_at_infinity() { return self.position }
+        self.position / self.position.w
+    }
+
+    // x, y and z over w, the point the homogeneous position stands for. At infinity this is
+    // the direction, x, y and z as they are.
+    pub fn point(&self) -> Vector3<f32> {
+        self.normalized_position().xyz()
+    }
+
+    pub fn is_at_infinity(&self) -> bool {
+        self.position.w == 0.0
+    }
+
+    // Of the point, so positions differing only by a scale of the whole vector match
+    fn position_bits(&self) -> [u32; 3] {
+        self.point().map(f32::to_bits).into()
+    }
+
+    // Bitwise key used to deduplicate vertices, f32 isn't Hash/Eq
+    fn bits(&self) -> Vec<u32> {
+        let mut bits = self.surface_bits();
+        bits.extend(self.tangent.iter().map(|value| value.to_bits()));
+        bits.extend(self.bone_indices);
+        bits.extend(self.bone_weights.iter().map(|value| value.to_bits()));
+        bits
+    }
+
+    fn surface_bits(&self) -> Vec<u32> {
+        self.position.iter()
+            .chain(self.texture_coords.iter())
+            .chain(self.normals.iter())
+            .map(|value| value.to_bits())
+            .collect()
+    }
 }
 
 
 
+#[derive(Default, Copy, Clone)]
+pub struct ObjLoadOptions {
+    // Reverses each face's vertex order, for files authored clockwise that come out inside out
+    // with backface culling. Normals in the file are kept as they are.
+    pub flip_winding: bool,
+}
+
 pub struct ObjLoader {
+    options: ObjLoadOptions,
     positions: Vec<Vector4<f32>>,
     texture_coords: Vec<Vector3<f32>>,
     normals: Vec<Vector3<f32>>,
+    // By position index, from the non-standard `vw <position> <bone> <weight> ...` statement
+    bone_weights: HashMap<i32, ([u32; 4], Vector4<f32>)>,
 
     meshes: Vec<ObjMesh>,
+    material_libraries: Vec<String>,
+    current_material: Option<String>,
+    materials: HashMap<String, Material>,
 
     // Warnings
-    mtllib_is_not_supported: bool,
-    mtl_is_not_supported: bool,
     groups_are_not_supported: bool,
 }
 
+#[derive(Debug)]
+pub enum ObjError {
+    Io { path: PathBuf, source: io::Error },
+    // A referenced .mtl library or one of its textures failed to load
+    Material(MaterialError),
+}
+
+impl Display for ObjError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "Failed to read model {}: {source}", path.display()),
+            ObjError::Material(source) => write!(f, "Failed to load the model's materials: {source}"),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Material(source) => Some(source),
+        }
+    }
+}
+
+impl From<MaterialError> for ObjError {
+    fn from(value: MaterialError) -> Self {
+        ObjError::Material(value)
+    }
+}
+
+// Meshes with their materials resolved, material texture slots index into textures
+pub struct ObjModel {
+    pub meshes: Vec<Mesh>,
+    pub textures: Vec<Texture2D>,
+}
+
 impl ObjLoader {
     pub fn new() -> Self {
+        Self::with_options(ObjLoadOptions::default())
+    }
+
+    pub fn with_options(options: ObjLoadOptions) -> Self {
         Self {
+            options,
             positions: Vec::new(),
             texture_coords: Vec::new(),
             normals: Vec::new(),
+            bone_weights: HashMap::new(),
             meshes: Vec::new(),
+            material_libraries: Vec::new(),
+            current_material: None,
+            materials: HashMap::new(),
 
-            mtllib_is_not_supported: false,
-            mtl_is_not_supported: false,
             groups_are_not_supported: false,
         }
     }
 
+    // Loads the .obj and the .mtl libraries it references, both relative to the .obj
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<ObjModel, ObjError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|source| ObjError::Io { path: path.to_path_buf(), source })?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        self.read(BufReader::new(file));
+
+        let mut library = MaterialLibrary::default();
+        for material_library in &self.material_libraries {
+            library.extend(MtlLoader::load(directory.join(material_library))?);
+        }
+        self.set_materials(&library);
+
+        Ok(ObjModel {
+            meshes: self.build(),
+            textures: library.textures,
+        })
+    }
+
+    // Materials usemtl statements are resolved against by parse, names not found get the default material
+    pub fn set_materials(&mut self, library: &MaterialLibrary) {
+        self.materials = library.materials.clone();
+    }
+
+    pub fn material_libraries(&self) -> &[String] {
+        &self.material_libraries
+    }
+
     pub fn parse(&mut self, reader: impl BufRead) -> Vec<Mesh> {
+        self.read(reader);
+        self.build()
+    }
+
+    fn read(&mut self, reader: impl BufRead) {
         self.positions.clear();
         self.texture_coords.clear();
         self.normals.clear();
+        self.bone_weights.clear();
         self.meshes.clear();
-        self.mtllib_is_not_supported = false;
-        self.mtl_is_not_supported = false;
+        self.material_libraries.clear();
+        self.current_material = None;
         self.groups_are_not_supported = false;
 
 
@@ -98,15 +526,9 @@ impl ObjLoader {
 
             self.parse_line(&line);
         }
+    }
 
-        if self.texture_coords.is_empty() {
-            self.texture_coords.push(Vector3::new(0.0, 0.0, 1.0))
-        }
-
-        if self.normals.is_empty() {
-            self.normals.push(Vector3::new(0.0, 0.0, 1.0))
-        }
-
+    fn build(&mut self) -> Vec<Mesh> {
         let mut meshes = Vec::with_capacity(self.meshes.len());
 
         for mut mesh in self.meshes.drain(..) {
@@ -116,22 +538,34 @@ impl ObjLoader {
                     let vert = face.vertex_indices[i];
 
                     let position = self.positions[vert.position_index as usize - 1];
-                    let texture_coords = self.texture_coords[vert.texcoords_index as usize - 1];
-                    let normals = self.normals[vert.normal_index as usize - 1];
+                    let texture_coords = vert.texcoords_index
+                        .map_or(Vector3::new(0.0, 0.0, 1.0), |index| self.texture_coords[index as usize - 1]);
+                    let normals = vert.normal_index
+                        .map_or(Vector3::new(0.0, 0.0, 1.0), |index| self.normals[index as usize - 1]);
+                    let (bone_indices, bone_weights) = self.bone_weights.get(&vert.position_index)
+                        .copied()
+                        .unwrap_or(([0; 4], Vector4::zeros()));
 
                     mesh_face.vertices[i] = Vertex {
                         position,
                         texture_coords,
                         normals,
+                        tangent: Vector4::new(1.0, 0.0, 0.0, 1.0),
+                        bone_indices,
+                        bone_weights,
                     };
                 }
+                if self.options.flip_winding {
+                    mesh_face.vertices.swap(1, 2);
+                }
                 mesh_face
             }).collect::<Vec<_>>();
 
-            meshes.push(Mesh {
-                name: mesh.name,
-                faces,
-            })
+            let mut built_mesh = Mesh::new(mesh.name, faces);
+            if let Some(material) = mesh.material.and_then(|name| self.materials.get(&name)) {
+                built_mesh.material = *material;
+            }
+            meshes.push(built_mesh)
         }
 
         meshes
@@ -146,18 +580,18 @@ impl ObjLoader {
         };
 
         match line_prefix {
-            "#" => return,
+            "#" => {},
             "v" => self.parse_position(words),
             "vt" => self.parse_texture_coords(words),
             "vn" => self.parse_normal(words),
+            "vw" => self.parse_bone_weights(words),
             "f" => self.parse_face(words),
             "o" => self.parse_object(line.trim_start_matches("o ")),
-            "mtllib" => self.mtllib_is_not_supported = true,
-            "usemtl" => self.mtl_is_not_supported = true,
+            "mtllib" => self.material_libraries.extend(words.map(str::to_string)),
+            "usemtl" => self.parse_use_material(line.trim().trim_start_matches("usemtl").trim()),
             "g" => self.groups_are_not_supported = true,
             _ => {
                 // If invalid we just skip the line
-                return;
             },
         }
     }
@@ -181,9 +615,8 @@ impl ObjLoader {
         let Some(u) = word.next() else { return };
         let Ok(u) = u.parse::<f32>() else { return };
 
-        let Some(v) = word.next() else { return };
-        let Ok(v) = v.parse::<f32>() else { return };
-
+        // 1D textures only give u
+        let v = word.next().and_then(|v| v.parse::<f32>().ok()).unwrap_or(0.0);
         let w = word.next().and_then(|w| w.parse::<f32>().ok()).unwrap_or(1.0);
 
         self.texture_coords.push(Vector3::new(u, v, w));
@@ -202,11 +635,33 @@ impl ObjLoader {
         self.normals.push(Vector3::new(x, y, z));
     }
 
+    // Bone and weight pairs after the 1-based position index, pairs past the fourth are ignored
+    fn parse_bone_weights(&mut self, mut word: SplitWhitespace) {
+        let Some(position_index) = word.next() else { return };
+        let Ok(position_index) = position_index.parse::<i32>() else { return };
+
+        let mut bone_indices = [0; 4];
+        let mut bone_weights = Vector4::zeros();
+        for i in 0..4 {
+            let Some(bone) = word.next() else { break };
+            let Ok(bone) = bone.parse::<u32>() else { return };
+
+            let Some(weight) = word.next() else { return };
+            let Ok(weight) = weight.parse::<f32>() else { return };
+
+            bone_indices[i] = bone;
+            bone_weights[i] = weight;
+        }
+
+        self.bone_weights.insert(position_index, (bone_indices, bone_weights));
+    }
+
     fn parse_face(&mut self, mut word: SplitWhitespace) {
         if self.meshes.is_empty() {
             self.meshes.push(ObjMesh {
                 name: None,
                 faces: Vec::new(),
+                material: self.current_material.clone(),
             });
         }
 
@@ -226,11 +681,12 @@ impl ObjLoader {
     fn parse_face_indices(&mut self, word: &str) -> Option<ObjFaceIndex> {
         let mut vertex_indices = word.split('/');
 
-        let Some(position_index) = vertex_indices.next() else { return None };
+        let position_index = vertex_indices.next()?;
         let Ok(position_index) = position_index.parse::<i32>() else { return None };
 
-        let texcoords_index = vertex_indices.next().and_then(|i| i.parse::<i32>().ok()).unwrap_or(1);
-        let normal_index = vertex_indices.next().and_then(|i| i.parse::<i32>().ok()).unwrap_or(1);
+        // Handles v, v/vt, v//vn and v/vt/vn, an empty or missing segment means that component is absent
+        let texcoords_index = vertex_indices.next().and_then(|i| i.parse::<i32>().ok());
+        let normal_index = vertex_indices.next().and_then(|i| i.parse::<i32>().ok());
 
         Some(ObjFaceIndex {
             position_index,
@@ -243,13 +699,38 @@ impl ObjLoader {
         self.meshes.push(ObjMesh {
             name: Some(name.to_string()),
             faces: Vec::new(),
+            material: self.current_material.clone(),
         });
     }
+
+    // Each material change starts a new mesh for the faces after it, within the same object
+    fn parse_use_material(&mut self, material: &str) {
+        self.current_material = Some(material.to_string());
+
+        match self.meshes.last_mut() {
+            Some(mesh) if mesh.faces.is_empty() => mesh.material = self.current_material.clone(),
+            last => {
+                let name = last.and_then(|mesh| mesh.name.clone());
+                self.meshes.push(ObjMesh {
+                    name,
+                    faces: Vec::new(),
+                    material: self.current_material.clone(),
+                });
+            }
+        }
+    }
+}
+
+impl Default for ObjLoader {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 struct ObjMesh {
     name: Option<String>,
     faces: Vec<ObjFace>,
+    material: Option<String>,
 }
 
 #[derive(Default, Copy, Clone)]
@@ -260,6 +741,146 @@ pub struct ObjFace {
 #[derive(Default, Copy, Clone)]
 pub struct ObjFaceIndex {
     position_index: i32,
-    texcoords_index: i32,
-    normal_index: i32,
-}
\ No newline at end of file
+    texcoords_index: Option<i32>,
+    normal_index: Option<i32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+    use nalgebra::{Translation3, Vector2, Vector3};
+    use super::{Face, Mesh, ObjError, ObjLoadOptions, ObjLoader, Quad, QuadMesh, Vertex};
+
+    #[test]
+    fn missing_model_is_not_a_material_error() {
+        let error = ObjLoader::new().load("missing_model.obj").err().unwrap();
+        assert!(matches!(error, ObjError::Io { .. }), "{error}");
+    }
+
+    #[test]
+    fn missing_material_library_is_a_material_error() {
+        let path = std::env::temp_dir().join(format!("simple_raster_missing_mtl_{}.obj", std::process::id()));
+        fs::write(&path, "mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+
+        let result = ObjLoader::new().load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(ObjError::Material(_))));
+    }
+
+    // Wound counter-clockwise seen from outside, centred on the origin
+    fn tetrahedron() -> Mesh {
+        let corners = [Vector3::new(1.0, 1.0, 1.0), Vector3::new(1.0, -1.0, -1.0), Vector3::new(-1.0, 1.0, -1.0), Vector3::new(-1.0, -1.0, 1.0)];
+        let face = |indices: [usize; 3]| Face::new(indices.map(|index| Vertex::from_pos(corners[index].push(1.0))));
+        Mesh::new(None, vec![face([0, 3, 1]), face([0, 1, 2]), face([0, 2, 3]), face([1, 3, 2])])
+    }
+
+    #[test]
+    fn smooth_normals_point_out_of_a_tetrahedron() {
+        let mut mesh = tetrahedron();
+        mesh.compute_smooth_normals();
+
+        for vertex in mesh.faces.iter().flat_map(|face| &face.vertices) {
+            let outward = vertex.point().normalize();
+            assert!((vertex.normals - outward).norm() < 1e-5, "{:?} at {:?}", vertex.normals, vertex.point());
+        }
+    }
+
+    #[test]
+    fn unit_cube_is_centred_on_the_origin() {
+        let cube = Mesh::cube();
+
+        assert!(cube.centroid().norm() < 1e-6, "{:?}", cube.centroid());
+        assert_eq!(cube.bounding_box(), (Vector3::repeat(-0.5), Vector3::repeat(0.5)));
+    }
+
+    #[test]
+    fn translating_moves_positions_and_keeps_normals() {
+        let original = Mesh::quad();
+        let mut moved = Mesh::quad();
+        moved.transform(&Translation3::new(1.0, 2.0, 3.0).to_homogeneous());
+
+        let vertices = |mesh: &Mesh| mesh.faces.iter().flat_map(|face| face.vertices).collect::<Vec<_>>();
+        for (before, after) in vertices(&original).iter().zip(vertices(&moved)) {
+            assert_eq!(after.point(), before.point() + Vector3::new(1.0, 2.0, 3.0));
+            assert_eq!(after.position.w, 1.0);
+            assert_eq!(after.normals, before.normals);
+            assert_eq!(after.tangent, before.tangent);
+        }
+    }
+
+    #[test]
+    fn merging_two_quads_keeps_the_first_material() {
+        let mut merged = Mesh::quad();
+        merged.material.opacity = 0.5;
+        let mut other = Mesh::quad();
+        other.material.opacity = 0.25;
+
+        merged.merge(&other);
+
+        assert_eq!(merged.faces.len(), 4);
+        assert_eq!(merged.material.opacity, 0.5);
+    }
+
+    #[test]
+    fn faces_read_every_index_form() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.25 0.75\nvn 0 1 0\n\
+            f 1 2 3\nf 1/1 2/1 3/1\nf 1//1 2//1 3//1\nf 1/1/1 2/1/1 3/1/1\n";
+        let meshes = ObjLoader::new().parse(Cursor::new(obj));
+        let (no_uv, uv) = (Vector2::zeros(), Vector2::new(0.25, 0.75));
+        let (no_normal, normal) = (Vector3::z(), Vector3::y());
+
+        let expected = [(no_uv, no_normal), (uv, no_normal), (no_uv, normal), (uv, normal)];
+        assert_eq!(meshes[0].faces.len(), expected.len());
+        for (face, (texture_coords, normals)) in meshes[0].faces.iter().zip(expected) {
+            assert_eq!(face.vertices.map(|vertex| vertex.point()), [Vector3::zeros(), Vector3::x(), Vector3::y()]);
+            for vertex in &face.vertices {
+                assert_eq!((vertex.texture_coords.xy(), vertex.normals), (texture_coords, normals));
+            }
+        }
+    }
+
+    #[test]
+    fn texture_coords_with_only_u_default_v_to_zero() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5\nf 1/1 2/1 3/1\n";
+        let meshes = ObjLoader::new().parse(Cursor::new(obj));
+
+        for vertex in &meshes[0].faces[0].vertices {
+            assert_eq!(vertex.texture_coords.xy(), Vector2::new(0.5, 0.0));
+        }
+    }
+
+    #[test]
+    fn quads_split_into_two_triangles_keeping_their_winding() {
+        let corner = |x: f32, y: f32| Vertex::from_pos(Vector3::new(x, y, 0.0).push(1.0));
+        let square = |x: f32| Quad::new([corner(x, 0.0), corner(x + 1.0, 0.0), corner(x + 1.0, 1.0), corner(x, 1.0)]);
+        let mut quad_mesh = QuadMesh::new(Some("squares".to_string()), vec![square(0.0), square(2.0)]);
+        quad_mesh.material.opacity = 0.5;
+
+        let mesh = quad_mesh.to_mesh();
+
+        assert_eq!(mesh.faces.len(), 4);
+        assert_eq!(mesh.name.as_deref(), Some("squares"));
+        assert_eq!(mesh.material.opacity, 0.5);
+        for (quad, faces) in quad_mesh.quads.iter().zip(mesh.faces.chunks(2)) {
+            for (face, indices) in faces.iter().zip([[0, 1, 2], [0, 2, 3]]) {
+                assert_eq!(face.vertices.map(|vertex| vertex.position), indices.map(|index| quad.vertices[index].position));
+                assert!(face.geometric_normal().z > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn flip_winding_reverses_vertex_order() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+        let positions = |flip_winding: bool| {
+            let meshes = ObjLoader::with_options(ObjLoadOptions { flip_winding }).parse(Cursor::new(obj));
+            meshes[0].faces[0].vertices.map(|vertex| vertex.point())
+        };
+
+        let [a, b, c] = positions(false);
+        assert_eq!(positions(true), [a, c, b]);
+        assert!(Face::new(positions(true).map(|point| Vertex::from_pos(point.push(1.0)))).geometric_normal().z < 0.0);
+    }
+}
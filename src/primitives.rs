@@ -0,0 +1,160 @@
+use std::f32::consts::PI;
+use nalgebra::{Vector2, Vector3, Vector4};
+use crate::mesh::{Face, Mesh, Vertex};
+
+// All primitives are centred on the origin, fit inside a unit cube and wind counter-clockwise
+// when viewed from outside
+impl Mesh {
+    // In the xy plane facing +z
+    pub fn quad() -> Self {
+        let faces = quad_faces(Vector3::zeros(), Vector3::x(), Vector3::y(), Vector3::z(), [Vector2::zeros(), Vector2::new(1.0, 1.0)]);
+        Mesh::new(Some("quad".to_string()), faces.to_vec())
+    }
+
+    // Spanning centre ± right/2 ± up/2 rather than centred on the origin, facing along right x up
+    pub fn quad_spanning(centre: Vector3<f32>, right: Vector3<f32>, up: Vector3<f32>) -> Self {
+        let normal = right.cross(&up).try_normalize(f32::EPSILON).unwrap_or(Vector3::z());
+        let faces = quad_faces(centre, right, up, normal, [Vector2::zeros(), Vector2::new(1.0, 1.0)]);
+        Mesh::new(Some("quad".to_string()), faces.to_vec())
+    }
+
+    pub fn cube() -> Self {
+        let sides = [
+            (Vector3::x(), -Vector3::z(), Vector3::y()),
+            (-Vector3::x(), Vector3::z(), Vector3::y()),
+            (Vector3::y(), Vector3::x(), -Vector3::z()),
+            (-Vector3::y(), Vector3::x(), Vector3::z()),
+            (Vector3::z(), Vector3::x(), Vector3::y()),
+            (-Vector3::z(), -Vector3::x(), Vector3::y()),
+        ];
+
+        let faces = sides.iter()
+            .flat_map(|(normal, right, up)| quad_faces(normal * 0.5, *right, *up, *normal, [Vector2::zeros(), Vector2::new(1.0, 1.0)]))
+            .collect();
+
+        Mesh::new(Some("cube".to_string()), faces)
+    }
+
+    // In the xz plane facing +y, split into subdivisions x subdivisions cells
+    pub fn plane(subdivisions: usize) -> Self {
+        let subdivisions = subdivisions.max(1);
+        let cell_size = 1.0 / subdivisions as f32;
+
+        let mut faces = Vec::with_capacity(subdivisions * subdivisions * 2);
+        for row in 0..subdivisions {
+            for column in 0..subdivisions {
+                let uv_min = Vector2::new(column as f32, row as f32) * cell_size;
+                let uv_max = uv_min + Vector2::new(cell_size, cell_size);
+                let centre = Vector3::new(uv_min.x + cell_size / 2.0 - 0.5, 0.0, 0.5 - uv_min.y - cell_size / 2.0);
+
+                faces.extend(quad_faces(centre, Vector3::x() * cell_size, -Vector3::z() * cell_size, Vector3::y(), [uv_min, uv_max]));
+            }
+        }
+
+        Mesh::new(Some("plane".to_string()), faces)
+    }
+
+    // Radius 0.5 with the poles on the y axis, segments run around it and rings from pole to pole
+    pub fn uv_sphere(segments: usize, rings: usize) -> Self {
+        let segments = segments.max(3);
+        let rings = rings.max(2);
+
+        let sphere_vertex = |ring: usize, segment: usize| {
+            let theta = PI * ring as f32 / rings as f32;
+            let phi = 2.0 * PI * segment as f32 / segments as f32;
+            let normal = Vector3::new(theta.sin() * phi.sin(), theta.cos(), theta.sin() * phi.cos());
+            let uv = Vector2::new(segment as f32 / segments as f32, 1.0 - ring as f32 / rings as f32);
+
+            vertex(normal * 0.5, uv, normal)
+        };
+
+        let mut faces = Vec::with_capacity(segments * rings * 2);
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let top_left = sphere_vertex(ring, segment);
+                let bottom_left = sphere_vertex(ring + 1, segment);
+                let bottom_right = sphere_vertex(ring + 1, segment + 1);
+                let top_right = sphere_vertex(ring, segment + 1);
+
+                // The triangle touching a pole collapses to a line, so it's left out
+                if ring != rings - 1 {
+                    faces.push(Face::new([top_left, bottom_left, bottom_right]));
+                }
+                if ring != 0 {
+                    faces.push(Face::new([top_left, bottom_right, top_right]));
+                }
+            }
+        }
+
+        Mesh::new(Some("uv_sphere".to_string()), faces)
+    }
+}
+
+// Two triangles spanning centre ± right/2 ± up/2, with right x up pointing along normal
+fn quad_faces(centre: Vector3<f32>, right: Vector3<f32>, up: Vector3<f32>, normal: Vector3<f32>, [uv_min, uv_max]: [Vector2<f32>; 2]) -> [Face; 2] {
+    let bottom_left = vertex(centre - right / 2.0 - up / 2.0, uv_min, normal);
+    let bottom_right = vertex(centre + right / 2.0 - up / 2.0, Vector2::new(uv_max.x, uv_min.y), normal);
+    let top_right = vertex(centre + right / 2.0 + up / 2.0, uv_max, normal);
+    let top_left = vertex(centre - right / 2.0 + up / 2.0, Vector2::new(uv_min.x, uv_max.y), normal);
+
+    [
+        Face::new([bottom_left, bottom_right, top_right]),
+        Face::new([bottom_left, top_right, top_left]),
+    ]
+}
+
+fn vertex(position: Vector3<f32>, uv: Vector2<f32>, normal: Vector3<f32>) -> Vertex {
+    Vertex {
+        position: position.push(1.0),
+        texture_coords: uv.push(0.0),
+        normals: normal,
+        tangent: Vector4::new(1.0, 0.0, 0.0, 1.0),
+        bone_indices: [0; 4],
+        bone_weights: Vector4::zeros(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use crate::mesh::Mesh;
+
+    // Unit length normals on the side the faces wind counter-clockwise towards
+    fn assert_faces(mesh: &Mesh, expected_faces: usize) {
+        assert_eq!(mesh.faces.len(), expected_faces, "{:?}", mesh.name);
+
+        for face in &mesh.faces {
+            let geometric_normal = face.geometric_normal();
+            for vertex in &face.vertices {
+                assert!((vertex.normals.norm() - 1.0).abs() < 1e-5, "{:?} normal {:?}", mesh.name, vertex.normals);
+                assert!(vertex.normals.dot(&geometric_normal) > 0.0, "{:?} face winds away from {:?}", mesh.name, vertex.normals);
+            }
+        }
+    }
+
+    #[test]
+    fn quad() {
+        assert_faces(&Mesh::quad(), 2);
+    }
+
+    #[test]
+    fn quad_spanning() {
+        assert_faces(&Mesh::quad_spanning(Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.0, 0.0, 4.0), Vector3::new(0.0, 3.0, 0.0)), 2);
+    }
+
+    #[test]
+    fn cube() {
+        assert_faces(&Mesh::cube(), 12);
+    }
+
+    #[test]
+    fn plane() {
+        assert_faces(&Mesh::plane(3), 18);
+    }
+
+    // Rings touching the poles have one triangle per segment instead of two
+    #[test]
+    fn uv_sphere() {
+        assert_faces(&Mesh::uv_sphere(8, 4), 2 * 8 * 4 - 2 * 8);
+    }
+}
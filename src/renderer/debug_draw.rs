@@ -0,0 +1,147 @@
+use nalgebra::{Matrix4, Vector3, Vector4};
+use crate::renderer::rasterizer::framebuffer::Framebuffer;
+
+// Keeps line ends off the camera plane where the perspective divide blows up
+const MIN_CLIP_W: f32 = 1e-5;
+
+struct DebugLine {
+    start: Vector3<f32>,
+    end: Vector3<f32>,
+    colour: Vector3<f32>,
+}
+
+// World space lines drawn over the finished frame, after post processing, with no depth test and
+// no shader. Collected through a frame and cleared once the renderer has drawn them.
+#[derive(Default)]
+pub struct DebugDraw {
+    lines: Vec<DebugLine>,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn line(&mut self, start: Vector3<f32>, end: Vector3<f32>, colour: Vector3<f32>) {
+        self.lines.push(DebugLine { start, end, colour });
+    }
+
+    // The box's twelve edges
+    pub fn aabb(&mut self, min: Vector3<f32>, max: Vector3<f32>, colour: Vector3<f32>) {
+        let corner = |corner: usize| Vector3::new(
+            if corner & 1 == 0 { min.x } else { max.x },
+            if corner & 2 == 0 { min.y } else { max.y },
+            if corner & 4 == 0 { min.z } else { max.z },
+        );
+
+        // Each corner joined to the corners differing from it in one axis
+        for start in 0..8 {
+            for axis in [1, 2, 4] {
+                if start & axis == 0 {
+                    self.line(corner(start), corner(start | axis), colour);
+                }
+            }
+        }
+    }
+
+    // An x, y and z line from origin in red, green and blue
+    pub fn axes(&mut self, origin: Vector3<f32>, length: f32) {
+        for axis in 0..3 {
+            let direction = Vector3::ith(axis, 1.0);
+            self.line(origin, origin + direction * length, direction);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    // Projects with view_projection onto the whole width by height buffer
+    pub(crate) fn draw(&self, buffer: &mut [u32], width: usize, height: usize, view_projection: &Matrix4<f32>) {
+        for line in &self.lines {
+            let start = view_projection * line.start.push(1.0);
+            let end = view_projection * line.end.push(1.0);
+            let Some((start, end)) = Self::clip(start, end) else { continue };
+
+            let to_screen = |position: Vector4<f32>| {
+                let ndc = position.xy() / position.w;
+                ((ndc.x + 1.0) / 2.0 * width as f32, (1.0 - ndc.y) / 2.0 * height as f32)
+            };
+            let colour = Framebuffer::convert_colour_to_u32(line.colour);
+            Self::draw_line(buffer, width, height, to_screen(start), to_screen(end), colour);
+        }
+    }
+
+    // Against the sides of the view and just in front of the camera, the line's depth is
+    // otherwise ignored so nothing is clipped at the near or far planes
+    fn clip(start: Vector4<f32>, end: Vector4<f32>) -> Option<(Vector4<f32>, Vector4<f32>)> {
+        let planes: [fn(&Vector4<f32>) -> f32; 5] = [
+            |p| p.w + p.x,
+            |p| p.w - p.x,
+            |p| p.w + p.y,
+            |p| p.w - p.y,
+            |p| p.w - MIN_CLIP_W,
+        ];
+
+        let mut t_start = 0.0_f32;
+        let mut t_end = 1.0_f32;
+        for plane in planes {
+            let start_distance = plane(&start);
+            let end_distance = plane(&end);
+
+            if start_distance < 0.0 && end_distance < 0.0 { return None }
+
+            let t = start_distance / (start_distance - end_distance);
+            if start_distance < 0.0 {
+                t_start = t_start.max(t);
+            } else if end_distance < 0.0 {
+                t_end = t_end.min(t);
+            }
+        }
+
+        if t_start > t_end { return None }
+        Some((start.lerp(&end, t_start), start.lerp(&end, t_end)))
+    }
+
+    // One pixel per step along the longer axis, sampling pixel centres
+    fn draw_line(buffer: &mut [u32], width: usize, height: usize, start: (f32, f32), end: (f32, f32), colour: u32) {
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as usize;
+
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            let x = (start.0 + dx * t).floor();
+            let y = (start.1 + dy * t).floor();
+            if x < 0.0 || y < 0.0 || x >= width as f32 || y >= height as f32 { continue }
+
+            buffer[y as usize * width + x as usize] = colour;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix4, Vector3};
+    use crate::renderer::rasterizer::test_support::{full_screen_triangle, plain_renderer_options, SolidShader};
+    use crate::renderer::Renderer;
+
+    #[test]
+    fn lines_show_over_solid_geometry() {
+        let mut renderer = Renderer::new_headless(16, 16, plain_renderer_options());
+        renderer.rasterizer.storage_mut().set_mat4(0, Matrix4::identity());
+        renderer.rasterizer.draw_mesh(&full_screen_triangle(0.0), &SolidShader::red()).unwrap();
+
+        // Across the middle row and behind the triangle, which lines don't depth test against
+        renderer.debug_draw.line(Vector3::new(-1.0, -0.0625, 0.5), Vector3::new(1.0, -0.0625, 0.5), Vector3::y());
+        renderer.render_frame();
+
+        for (y, row) in renderer.frame().chunks(16).enumerate() {
+            let expected = if y == 8 { 0x00ff00 } else { 0xff0000 };
+            assert!(row.iter().all(|&pixel| pixel == expected), "Row {y} is {row:x?}");
+        }
+    }
+}
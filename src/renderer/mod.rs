@@ -1,8 +1,18 @@
+use std::path::Path;
+use std::time::Instant;
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{ImageError, ImageFormat, ImageResult, Rgba, RgbaImage};
+use nalgebra::Vector3;
+use crate::renderer::debug_draw::DebugDraw;
 use crate::renderer::post_processor::{PostProcessor, PostProcessorOptions};
-use crate::renderer::rasterizer::{RasterOptions, Rasterizer};
+use crate::renderer::rasterizer::{install, RasterOptions, Rasterizer};
+use crate::renderer::rasterizer::storage::BindingError;
+use crate::renderer::scene::Scene;
 
 pub mod rasterizer;
 pub mod post_processor;
+pub mod scene;
+pub mod debug_draw;
 
 
 
@@ -10,27 +20,262 @@ pub mod post_processor;
 pub struct RendererOptions {
     pub raster_options: RasterOptions,
     pub post_processor_options: PostProcessorOptions,
+    // Rasterizes at this fraction of the output size and upscales, 1.0 renders at full size
+    pub render_scale: f32,
+    // Rasterizes at this many times the width and height and box filters back down, 1 disables it.
+    // Rasterization time and memory grow with the square of the factor.
+    pub supersample: u32,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RenderStats {
+    pub rasterize_ns: u128,
+    pub postprocess_ns: u128,
 }
 
 pub struct Renderer {
+    width: usize,
+    height: usize,
+    render_scale: f32,
+    supersample: usize,
+    scaled_buffer: Vec<u32>,
+    supersampled_buffer: Vec<u32>,
+    // Only headless renderers own their output, windowed ones render into the caller's buffer
+    frame: Option<Vec<u32>>,
     pub rasterizer: Rasterizer,
     post_processor: PostProcessor,
+    // Drawn over each rendered frame with the view projection in mat4 0, then cleared
+    pub debug_draw: DebugDraw,
 }
 
 impl Renderer {
     pub fn new(width: usize, height: usize, options: RendererOptions) -> Self {
+        let (scaled_width, scaled_height) = Self::scaled_size(width, height, options.render_scale);
+        let supersample = options.supersample.max(1) as usize;
+
         Self {
-            rasterizer: Rasterizer::new(width, height, options.raster_options),
+            width,
+            height,
+            render_scale: options.render_scale,
+            supersample,
+            scaled_buffer: vec![0; scaled_width * scaled_height],
+            supersampled_buffer: Self::build_supersampled_buffer(scaled_width, scaled_height, supersample),
+            frame: None,
+            rasterizer: Rasterizer::new(scaled_width * supersample, scaled_height * supersample, options.raster_options),
             post_processor: PostProcessor::new(width, height, options.post_processor_options),       
+            debug_draw: DebugDraw::new(),
         }
     }
     
-    pub fn render(&mut self, buffer: &mut [u32]) {
-        let now = std::time::Instant::now();
-        self.rasterizer.render_to_buffer(buffer);
-        println!("Rasterization took {} ns", now.elapsed().as_nanos());
-        let now = std::time::Instant::now();
-        self.post_processor.process(buffer);
-        println!("Post processing took {} ns", now.elapsed().as_nanos());
-    }
-}
\ No newline at end of file
+    pub fn new_headless(width: usize, height: usize, options: RendererOptions) -> Self {
+        Self {
+            frame: Some(vec![0; width * height]),
+            ..Self::new(width, height, options)
+        }
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let (scaled_width, scaled_height) = Self::scaled_size(width, height, self.render_scale);
+
+        self.width = width;
+        self.height = height;
+        self.scaled_buffer = vec![0; scaled_width * scaled_height];
+        self.supersampled_buffer = Self::build_supersampled_buffer(scaled_width, scaled_height, self.supersample);
+        if let Some(frame) = &mut self.frame {
+            *frame = vec![0; width * height];
+        }
+        self.rasterizer.resize(scaled_width * self.supersample, scaled_height * self.supersample);
+        self.post_processor.resize(width, height);
+    }
+
+    fn scaled_size(width: usize, height: usize, render_scale: f32) -> (usize, usize) {
+        let scaled_width = ((width as f32 * render_scale).round() as usize).max(1);
+        let scaled_height = ((height as f32 * render_scale).round() as usize).max(1);
+        (scaled_width, scaled_height)
+    }
+
+    // Left empty when not supersampling
+    fn build_supersampled_buffer(width: usize, height: usize, supersample: usize) -> Vec<u32> {
+        if supersample == 1 { return Vec::new() }
+        vec![0; width * height * supersample * supersample]
+    }
+
+    fn is_scaled(&self) -> bool {
+        self.scaled_buffer.len() != self.width * self.height
+    }
+
+    pub fn clear(&mut self) {
+        self.rasterizer.clear();
+    }
+
+    // Clears, draws every item in the scene and renders the result into the buffer
+    pub fn render_scene(&mut self, scene: &Scene, buffer: &mut [u32]) -> Result<RenderStats, BindingError> {
+        self.clear();
+        self.rasterizer.draw_scene(scene)?;
+        Ok(self.render(buffer))
+    }
+
+    // Renders into the owned frame, allocating one first if this renderer wasn't created headless
+    pub fn render_frame(&mut self) -> RenderStats {
+        let mut frame = self.frame.take().unwrap_or_else(|| vec![0; self.width * self.height]);
+        let stats = self.render(&mut frame);
+        self.frame = Some(frame);
+        stats
+    }
+
+    // Empty until render_frame has been called on a windowed renderer
+    pub fn frame(&self) -> &[u32] {
+        self.frame.as_deref().unwrap_or(&[])
+    }
+
+    // Renders as render does and unpacks the result into the image, opaque. The image must be
+    // the renderer's output size.
+    pub fn render_to_image(&mut self, image: &mut RgbaImage) -> ImageResult<RenderStats> {
+        if image.width() as usize != self.width || image.height() as usize != self.height {
+            return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+        }
+
+        let mut buffer = vec![0; self.width * self.height];
+        let stats = self.render(&mut buffer);
+
+        for (pixel, packed) in image.pixels_mut().zip(buffer) {
+            *pixel = Self::unpack_rgba(packed);
+        }
+        Ok(stats)
+    }
+
+    pub fn render(&mut self, buffer: &mut [u32]) -> RenderStats {
+        let thread_pool = self.rasterizer.thread_pool();
+        install(thread_pool.as_deref(), || self.render_in_pool(buffer))
+    }
+
+    fn render_in_pool(&mut self, buffer: &mut [u32]) -> RenderStats {
+        let (scaled_width, scaled_height) = Self::scaled_size(self.width, self.height, self.render_scale);
+        let is_scaled = self.is_scaled();
+
+        let now = Instant::now();
+        let target = if is_scaled { &mut self.scaled_buffer[..] } else { &mut buffer[..] };
+        if self.supersample > 1 {
+            self.rasterizer.render_to_buffer(&mut self.supersampled_buffer);
+            PostProcessor::downsample(&self.supersampled_buffer, self.supersample, scaled_width, target);
+        } else {
+            self.rasterizer.render_to_buffer(target);
+        }
+        let rasterize_ns = now.elapsed().as_nanos();
+        let now = Instant::now();
+        if is_scaled {
+            self.post_processor.upscale(&self.scaled_buffer, scaled_width, scaled_height, buffer);
+        }
+        let depths = self.post_processor.needs_depths().then(|| self.output_depths());
+        self.post_processor.process(buffer, depths.as_deref());
+        self.draw_debug_overlay(buffer);
+        let postprocess_ns = now.elapsed().as_nanos();
+
+        RenderStats {
+            rasterize_ns,
+            postprocess_ns,
+        }
+    }
+
+    // Skipped, though still cleared, when mat4 0 isn't set
+    fn draw_debug_overlay(&mut self, buffer: &mut [u32]) {
+        if self.debug_draw.is_empty() { return }
+
+        if let Some(view_projection) = self.rasterizer.storage().try_get_mat4(0) {
+            self.debug_draw.draw(buffer, self.width, self.height, view_projection);
+        }
+        self.debug_draw.clear();
+    }
+
+    // The rasterizer's depths sampled at the nearest pixel for each output pixel
+    fn output_depths(&self) -> Vec<f32> {
+        let framebuffer = self.rasterizer.framebuffer();
+        let distances = framebuffer.distances();
+        let (source_width, source_height) = (framebuffer.width(), framebuffer.height());
+
+        (0..self.width * self.height)
+            .map(|index| {
+                let x = index % self.width * source_width / self.width;
+                let y = index / self.width * source_height / self.height;
+                distances[y * source_width + x]
+            })
+            .collect()
+    }
+
+    // The frame as floats in whatever space the shaders wrote, unclamped and never quantized
+    // to 8 bits, for handing to another pipeline to encode. Post processing is skipped since it
+    // works on the packed 8 bit frame, supersampled and scaled frames are box filtered to size.
+    pub fn render_linear(&mut self, out: &mut [Vector3<f32>]) {
+        if out.len() != self.width * self.height {
+            panic!("Buffer length does not match image size");
+        }
+
+        let framebuffer = self.rasterizer.framebuffer();
+        let (source_width, source_height) = (framebuffer.width(), framebuffer.height());
+        let mut source = vec![Vector3::zeros(); source_width * source_height];
+        self.rasterizer.render_linear(&mut source);
+
+        // Each output pixel averages the source pixels it covers, at least the one it lands on
+        for (index, output) in out.iter_mut().enumerate() {
+            let (x, y) = (index % self.width, index / self.width);
+            let x_range = x * source_width / self.width..((x + 1) * source_width / self.width).max(x * source_width / self.width + 1);
+            let y_range = y * source_height / self.height..((y + 1) * source_height / self.height).max(y * source_height / self.height + 1);
+
+            let count = (x_range.len() * y_range.len()) as f32;
+            let sum = y_range
+                .flat_map(|source_y| x_range.clone().map(move |source_x| source_y * source_width + source_x))
+                .fold(Vector3::zeros(), |sum, source_index| sum + source[source_index]);
+            *output = sum / count;
+        }
+    }
+
+    // Writes the owned frame, an error when there isn't one because this renderer wasn't created
+    // headless and render_frame hasn't been called
+    pub fn save_png(&self, path: impl AsRef<Path>) -> ImageResult<()> {
+        let Some(frame) = &self.frame else {
+            return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic("No frame has been rendered to save".to_string()))));
+        };
+        save_buffer_png(frame, self.width, self.height, path)
+    }
+
+    fn unpack_rgba(pixel: u32) -> Rgba<u8> {
+        Rgba([(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8, 255])
+    }
+}
+
+// A buffer as packed by render, width by height pixels, written as an opaque png
+pub fn save_buffer_png(buffer: &[u32], width: usize, height: usize, path: impl AsRef<Path>) -> ImageResult<()> {
+    if buffer.len() != width * height {
+        return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+    }
+
+    let image = RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+        Renderer::unpack_rgba(buffer[y as usize * width + x as usize])
+    });
+
+    image.save_with_format(path, ImageFormat::Png)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use image::Rgba;
+    use crate::renderer::rasterizer::test_support::{full_screen_triangle, plain_renderer_options, SolidShader};
+    use super::Renderer;
+
+    #[test]
+    fn saves_the_rendered_frame() {
+        let path = std::env::temp_dir().join(format!("simple_raster_frame_{}.png", std::process::id()));
+        let mut renderer = Renderer::new(4, 4, plain_renderer_options());
+        assert!(renderer.save_png(&path).is_err());
+
+        renderer.rasterizer.draw_mesh(&full_screen_triangle(0.0), &SolidShader::red()).unwrap();
+        renderer.render_frame();
+        renderer.save_png(&path).unwrap();
+
+        let image = image::open(&path).unwrap().to_rgba8();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(image.dimensions(), (4, 4));
+        assert!(image.pixels().all(|&pixel| pixel == Rgba([255, 0, 0, 255])));
+    }
+}
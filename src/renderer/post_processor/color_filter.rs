@@ -0,0 +1,45 @@
+use nalgebra::{Matrix3, Vector3};
+
+// Colours are column vectors, so each row of the matrix produces one output channel:
+// [r', g', b'] = matrix * [r, g, b]
+#[derive(Copy, Clone)]
+pub struct ColorFilter {
+    pub matrix: Matrix3<f32>,
+}
+
+impl ColorFilter {
+    pub fn new(matrix: Matrix3<f32>) -> Self {
+        Self {
+            matrix,
+        }
+    }
+
+    pub fn grayscale() -> Self {
+        // Same luminance weights as PostProcessor::luminance, written to every channel
+        Self::new(Matrix3::new(
+            0.2126, 0.7152, 0.0722,
+            0.2126, 0.7152, 0.0722,
+            0.2126, 0.7152, 0.0722,
+        ))
+    }
+
+    pub fn sepia() -> Self {
+        Self::new(Matrix3::new(
+            0.393, 0.769, 0.189,
+            0.349, 0.686, 0.168,
+            0.272, 0.534, 0.131,
+        ))
+    }
+
+    pub fn apply(&self, pixel: u32) -> u32 {
+        let colour = Vector3::new(
+            ((pixel >> 16) & 0xff) as f32,
+            ((pixel >> 8) & 0xff) as f32,
+            (pixel & 0xff) as f32,
+        );
+
+        let filtered = (self.matrix * colour).map(|channel| channel.round().clamp(0.0, 255.0) as u32);
+
+        (filtered.x << 16) | (filtered.y << 8) | filtered.z
+    }
+}
@@ -1,7 +1,21 @@
+pub mod color_filter;
+pub mod outline;
+
 use rayon::prelude::*;
+use color_filter::ColorFilter;
+use outline::{Outline, OutlineSource};
 
 pub struct PostProcessorOptions {
     pub fxaa: bool,
+    pub sharpen: Option<f32>,
+    pub color_filter: Option<ColorFilter>,
+    // Red is sampled this many pixels outward from the centre and blue as far inward at the
+    // frame's corners, the offset shrinking linearly to nothing at the centre
+    pub chromatic_aberration: Option<f32>,
+    pub outline: Option<Outline>,
+    // Diagnostic, replaces the frame with opaque depth in grey, normalized to the nearest and
+    // farthest drawn this frame with near black and the background white. Other effects are skipped.
+    pub debug_depth: bool,
 }
 
 pub struct PostProcessor {
@@ -20,11 +34,119 @@ impl PostProcessor {
             buffer: vec![0; width * height],
         }
     }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.buffer = vec![0; width * height];
+    }
     
-    pub fn process(&mut self, buffer: &mut [u32]) {
+    // Only the depth view and an outline from depth read depths, see needs_depths
+    pub fn needs_depths(&self) -> bool {
+        self.options.debug_depth || matches!(self.options.outline, Some(Outline { source: OutlineSource::Depth, .. }))
+    }
+
+    // depths holds a value per pixel where larger is farther, as given by Framebuffer::distances
+    pub fn process(&mut self, buffer: &mut [u32], depths: Option<&[f32]>) {
+        if self.options.debug_depth {
+            if let Some(depths) = depths {
+                Self::run_debug_depth(buffer, depths, self.width);
+                return;
+            }
+        }
+
         if self.options.fxaa {
             self.run_fxaa(buffer);
         }
+
+        if let Some(strength) = self.options.sharpen {
+            if strength != 0.0 {
+                self.run_sharpen(buffer, strength);
+            }
+        }
+
+        if let Some(strength) = self.options.chromatic_aberration {
+            if strength != 0.0 {
+                self.run_chromatic_aberration(buffer, strength);
+            }
+        }
+
+        if let Some(outline) = self.options.outline {
+            self.run_outline(buffer, depths, &outline);
+        }
+
+        if let Some(color_filter) = self.options.color_filter {
+            Self::run_color_filter(buffer, self.width, &color_filter);
+        }
+    }
+
+    pub fn upscale(&self, source: &[u32], source_width: usize, source_height: usize, buffer: &mut [u32]) {
+        let scale_x = source_width as f32 / self.width as f32;
+        let scale_y = source_height as f32 / self.height as f32;
+
+        buffer.par_chunks_mut(self.width)
+            .enumerate()
+            .for_each(|(y, row)| {
+                // Sample at pixel centres so both buffers line up at their edges
+                let source_y = ((y as f32 + 0.5) * scale_y - 0.5).clamp(0.0, (source_height - 1) as f32);
+
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    let source_x = ((x as f32 + 0.5) * scale_x - 0.5).clamp(0.0, (source_width - 1) as f32);
+
+                    *pixel = Self::sample_bilinear(source, source_width, source_height, source_x, source_y);
+                }
+            });
+    }
+
+    // Box filters each factor by factor block of the source into one pixel of the buffer
+    pub fn downsample(source: &[u32], factor: usize, width: usize, buffer: &mut [u32]) {
+        let source_width = width * factor;
+        let sample_count = (factor * factor) as u32;
+
+        buffer.par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    let mut sums = [0_u32; 3];
+                    for source_y in y * factor..(y + 1) * factor {
+                        for source_x in x * factor..(x + 1) * factor {
+                            let sample = source[source_y * source_width + source_x];
+                            for (sum, shift) in sums.iter_mut().zip([16, 8, 0]) {
+                                *sum += (sample >> shift) & 0xff;
+                            }
+                        }
+                    }
+
+                    let [r, g, b] = sums.map(|sum| (sum + sample_count / 2) / sample_count);
+                    *pixel = (r << 16) | (g << 8) | b;
+                }
+            });
+    }
+
+    fn sample_bilinear(source: &[u32], width: usize, height: usize, x: f32, y: f32) -> u32 {
+        let x0 = x as usize;
+        let y0 = y as usize;
+        let x1 = (x0 + 1).min(width - 1);
+        let y1 = (y0 + 1).min(height - 1);
+        let tx = x - x0 as f32;
+        let ty = y - y0 as f32;
+
+        let top_left = source[y0 * width + x0];
+        let top_right = source[y0 * width + x1];
+        let bottom_left = source[y1 * width + x0];
+        let bottom_right = source[y1 * width + x1];
+
+        let mut result = 0;
+        for shift in [16, 8, 0] {
+            let channel = |pixel: u32| ((pixel >> shift) & 0xff) as f32;
+
+            let top = channel(top_left) * (1.0 - tx) + channel(top_right) * tx;
+            let bottom = channel(bottom_left) * (1.0 - tx) + channel(bottom_right) * tx;
+            let value = (top * (1.0 - ty) + bottom * ty).round() as u32;
+
+            result |= value.min(255) << shift;
+        }
+        result
     }
     
     fn run_fxaa(&mut self, buffer: &mut [u32]) {
@@ -52,6 +174,138 @@ impl PostProcessor {
         buffer.copy_from_slice(&self.buffer);
     }
     
+    fn run_sharpen(&mut self, buffer: &mut [u32], strength: f32) {
+        let width = self.width;
+        let height = self.height;
+
+        self.buffer.par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(y, row)| {
+                if y == 0 || y == height - 1 {
+                    row.copy_from_slice(&buffer[y * width..(y + 1) * width]);
+                    return;
+                }
+
+                for x in 0..width {
+                    if x == 0 || x == width - 1 {
+                        row[x] = buffer[y * width + x];
+                        continue;
+                    }
+
+                    row[x] = Self::sharpen_pixel(buffer, y * width + x, width, strength);
+                }
+            });
+
+        buffer.copy_from_slice(&self.buffer);
+    }
+
+    fn sharpen_pixel(buffer: &[u32], index: usize, width: usize, strength: f32) -> u32 {
+        let neighbours = [index - 1, index + 1, index - width, index + width];
+
+        let mut result = 0;
+        for shift in [16, 8, 0] {
+            let channel = |index: usize| ((buffer[index] >> shift) & 0xff) as f32;
+
+            let neighbour_sum: f32 = neighbours.iter().map(|&index| channel(index)).sum();
+            let value = channel(index) * (1.0 + 4.0 * strength) - neighbour_sum * strength;
+
+            result |= (value.round().clamp(0.0, 255.0) as u32) << shift;
+        }
+        result
+    }
+
+    fn run_chromatic_aberration(&mut self, buffer: &mut [u32], strength: f32) {
+        let width = self.width;
+        let height = self.height;
+        let centre_x = (width as f32 - 1.0) / 2.0;
+        let centre_y = (height as f32 - 1.0) / 2.0;
+        let corner_distance = (centre_x * centre_x + centre_y * centre_y).sqrt().max(f32::EPSILON);
+
+        self.buffer.par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    // Offset along the direction from the centre, strength pixels long at the corners
+                    let offset_x = (x as f32 - centre_x) / corner_distance * strength;
+                    let offset_y = (y as f32 - centre_y) / corner_distance * strength;
+
+                    // Border samples clamp to the nearest pixel in the frame
+                    let sample = |scale: f32| {
+                        let sample_x = (x as f32 + offset_x * scale).round().clamp(0.0, (width - 1) as f32) as usize;
+                        let sample_y = (y as f32 + offset_y * scale).round().clamp(0.0, (height - 1) as f32) as usize;
+                        buffer[sample_y * width + sample_x]
+                    };
+
+                    let red = sample(1.0) & 0xff0000;
+                    let green = buffer[y * width + x] & 0x00ff00;
+                    let blue = sample(-1.0) & 0x0000ff;
+                    *pixel = red | green | blue;
+                }
+            });
+
+        buffer.copy_from_slice(&self.buffer);
+    }
+
+    // Skipped for a depth outline when no depths were given
+    fn run_outline(&mut self, buffer: &mut [u32], depths: Option<&[f32]>, outline: &Outline) {
+        let width = self.width;
+        let height = self.height;
+        let colour = outline.colour_u32();
+
+        let value_at = |index: usize| match outline.source {
+            OutlineSource::Luminance => Some(Self::luminance(buffer[index])),
+            // Capped so the kernel's sums stay finite, background on both sides then cancels out
+            OutlineSource::Depth => depths.map(|depths| depths[index].min(f32::MAX / 16.0)),
+        };
+        if value_at(0).is_none() { return }
+
+        self.buffer.par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    // Neighbours past the border clamp to the edge pixels
+                    let gradient = Outline::gradient(|offset_x, offset_y| {
+                        let sample_x = x.saturating_add_signed(offset_x).min(width - 1);
+                        let sample_y = y.saturating_add_signed(offset_y).min(height - 1);
+                        value_at(sample_y * width + sample_x).unwrap_or(0.0)
+                    });
+
+                    *pixel = if gradient > outline.threshold { colour } else { buffer[y * width + x] };
+                }
+            });
+
+        buffer.copy_from_slice(&self.buffer);
+    }
+
+    fn run_debug_depth(buffer: &mut [u32], depths: &[f32], width: usize) {
+        let (near, far) = depths.iter()
+            .filter(|depth| **depth != f32::MAX)
+            .fold((f32::MAX, f32::MIN), |(near, far), depth| (near.min(*depth), far.max(*depth)));
+        let range = (far - near).max(f32::EPSILON);
+
+        buffer.par_chunks_mut(width)
+            .zip(depths.par_chunks(width))
+            .for_each(|(row, depths)| {
+                for (pixel, depth) in row.iter_mut().zip(depths) {
+                    let grey = if *depth == f32::MAX {
+                        u8::MAX
+                    } else {
+                        (((depth - near) / range).clamp(0.0, 1.0) * 255.0) as u8
+                    } as u32;
+                    *pixel = (grey << 16) | (grey << 8) | grey;
+                }
+            });
+    }
+
+    fn run_color_filter(buffer: &mut [u32], width: usize, color_filter: &ColorFilter) {
+        buffer.par_chunks_mut(width)
+            .for_each(|row| {
+                for pixel in row {
+                    *pixel = color_filter.apply(*pixel);
+                }
+            });
+    }
+
     fn run_fxaa_for_pixel(buffer: &[u32], row: &mut [u32], x: usize, y: usize, width: usize) {
         let index = y * width + x;
         
@@ -0,0 +1,44 @@
+use nalgebra::Vector3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutlineSource {
+    // Edges in the final colours, also catching texture and lighting detail
+    Luminance,
+    // Edges in the opaque depth, only silhouettes and depth steps. Pixels nothing was drawn
+    // to count as infinitely far so every silhouette against the background is outlined.
+    Depth,
+}
+
+// Pixels whose Sobel gradient magnitude is above the threshold are replaced by the colour.
+// Luminance runs from 0 to 1, depth is in the rasterizer's depth units.
+#[derive(Copy, Clone)]
+pub struct Outline {
+    pub source: OutlineSource,
+    pub threshold: f32,
+    pub colour: Vector3<f32>,
+}
+
+impl Outline {
+    pub fn new(source: OutlineSource, threshold: f32, colour: Vector3<f32>) -> Self {
+        Self {
+            source,
+            threshold,
+            colour,
+        }
+    }
+
+    // 3x3 Sobel over values read by x and y offset from the pixel, -1 to 1
+    pub fn gradient(value_at: impl Fn(isize, isize) -> f32) -> f32 {
+        let gradient_x = (value_at(1, -1) + 2.0 * value_at(1, 0) + value_at(1, 1))
+            - (value_at(-1, -1) + 2.0 * value_at(-1, 0) + value_at(-1, 1));
+        let gradient_y = (value_at(-1, 1) + 2.0 * value_at(0, 1) + value_at(1, 1))
+            - (value_at(-1, -1) + 2.0 * value_at(0, -1) + value_at(1, -1));
+
+        (gradient_x * gradient_x + gradient_y * gradient_y).sqrt()
+    }
+
+    pub fn colour_u32(&self) -> u32 {
+        let [r, g, b] = [self.colour.x, self.colour.y, self.colour.z].map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u32);
+        (r << 16) | (g << 8) | b
+    }
+}
@@ -1,61 +1,253 @@
-use std::collections::LinkedList;
 use nalgebra::{Vector3, Vector4};
+use crate::renderer::rasterizer::depth::DepthMode;
+use crate::renderer::rasterizer::TransparencyMode;
 
 #[derive(Copy, Clone)]
 pub struct Fragment {
     pub colour: Vector4<f32>,
     pub depth: f32,
+    // Clip space w, the distance from the camera plane under a perspective projection
+    pub w: f32,
 }
 
 pub struct RenderBufferPixel {
-    fragments: LinkedList<Fragment>,
+    fragments: Vec<Fragment>,
     background: Fragment,
 }
 
 impl RenderBufferPixel {
-    pub fn new(background_colour: Vector3<f32>) -> RenderBufferPixel {
+    pub fn new(depth_mode: DepthMode) -> RenderBufferPixel {
         RenderBufferPixel {
-            fragments: LinkedList::new(),
-            background: Fragment {
-                colour: background_colour.push(1.0),
-                depth: f32::MAX,
-            },
+            fragments: Vec::new(),
+            background: Self::empty_background(depth_mode),
+        }
+    }
+
+    // The colour is filled in from the Background at resolve time
+    fn empty_background(depth_mode: DepthMode) -> Fragment {
+        Fragment {
+            colour: Vector4::zeros(),
+            depth: depth_mode.clear_depth(),
+            w: 0.0,
         }
     }
     
-    pub fn add(&mut self, fragment: Fragment) {
-        if fragment.colour.w >= 0.9999 {
+    // Returns whether the fragment was opaque and replaced the background. Past max_fragments
+    // translucent fragments, see limit_fragments.
+    pub fn add(&mut self, fragment: Fragment, depth_mode: DepthMode, max_fragments: Option<usize>) -> bool {
+        let opaque = fragment.colour.w >= 0.9999;
+        if opaque {
             self.background = fragment;
         } else {
-            self.fragments.push_back(fragment);
+            self.fragments.push(fragment);
+            if let Some(max_fragments) = max_fragments.filter(|&max| self.fragments.len() > max) {
+                self.limit_fragments(max_fragments, depth_mode);
+            }
+        }
+        opaque
+    }
+
+    // Drops fragments behind the opaque background, which only ever gets nearer so they can't
+    // show, then merges the farthest two into one until there are max_fragments. The merge is
+    // exact for Sorted until a later fragment lands between the merged pair, while
+    // WeightedBlended weighs the pair at the nearer one's distance. At least one fragment is kept.
+    // Each merge is a pass over the fragments, they're left unsorted for resolve to sort.
+    fn limit_fragments(&mut self, max_fragments: usize, depth_mode: DepthMode) {
+        let background_distance = depth_mode.distance_key(self.background.depth);
+        self.fragments.retain(|fragment| depth_mode.distance_key(fragment.depth) <= background_distance || fragment.depth.is_nan());
+
+        let sort_depth = |fragment: &Fragment| if fragment.depth.is_nan() { f32::INFINITY } else { depth_mode.distance_key(fragment.depth) };
+        while self.fragments.len() > max_fragments.max(1) {
+            // The first two in resolve_sorted's order, farthest first and earlier first at equal depths
+            let (mut farthest, mut next) = if sort_depth(&self.fragments[1]) > sort_depth(&self.fragments[0]) { (1, 0) } else { (0, 1) };
+            for index in 2..self.fragments.len() {
+                let depth = sort_depth(&self.fragments[index]);
+                if depth > sort_depth(&self.fragments[farthest]) {
+                    (farthest, next) = (index, farthest);
+                } else if depth > sort_depth(&self.fragments[next]) {
+                    next = index;
+                }
+            }
+
+            // Kept in the nearer one's place, so it stays in order with fragments at its depth
+            self.fragments[next] = Self::composite(self.fragments[next], self.fragments[farthest]);
+            self.fragments.remove(farthest);
         }
     }
+
+    // nearer over farther as a single fragment at nearer's depth
+    fn composite(nearer: Fragment, farther: Fragment) -> Fragment {
+        let alpha = nearer.colour.w + farther.colour.w * (1.0 - nearer.colour.w);
+        let colour = (nearer.colour.xyz() * nearer.colour.w + farther.colour.xyz() * farther.colour.w * (1.0 - nearer.colour.w)) / alpha;
+
+        Fragment {
+            colour: colour.push(alpha),
+            depth: nearer.depth,
+            w: nearer.w,
+        }
+    }
+    
+    pub fn clear(&mut self, depth_mode: DepthMode) {
+        self.fragments.clear();
+        self.background = Self::empty_background(depth_mode);
+    }
     
-    pub fn resolve(&mut self, background_colour: Vector3<f32>) -> Vector3<f32> {
-        let mut fragments = self.fragments.iter().collect::<Vec<_>>();
-        fragments.sort_by(|a, b| a.depth.partial_cmp(&b.depth).unwrap());
+    // clear_colour is only evaluated when nothing opaque was drawn
+    pub fn resolve(&self, mode: TransparencyMode, depth_mode: DepthMode, clear_colour: impl FnOnce() -> Vector3<f32>) -> Vector3<f32> {
+        let background_colour = if self.is_empty(depth_mode) { clear_colour() } else { self.background.colour.xyz() };
 
-        let mut result_colour = self.background.colour.xyz();
-        let background_depth = self.background.depth;
+        match mode {
+            TransparencyMode::Sorted => self.resolve_sorted(background_colour, depth_mode),
+            TransparencyMode::WeightedBlended => self.resolve_weighted_blended(background_colour, depth_mode),
+        }
+    }
 
-        for fragment in fragments {
-            if fragment.depth > background_depth { continue }
+    // Nothing passes the depth test against the clear depth, so it only remains while undrawn
+    pub fn is_empty(&self, depth_mode: DepthMode) -> bool {
+        self.background.depth == depth_mode.clear_depth()
+    }
 
+    fn resolve_sorted(&self, background_colour: Vector3<f32>, depth_mode: DepthMode) -> Vector3<f32> {
+        let background_distance = depth_mode.distance_key(self.background.depth);
+        let mut fragments = self.fragments.iter()
+            .filter(|fragment| depth_mode.distance_key(fragment.depth) <= background_distance || fragment.depth.is_nan())
+            .collect::<Vec<_>>();
+
+        // Composited back to front, NaN depths count as farthest. The sort is stable so fragments
+        // at equal depths keep submission order and later ones end up on top.
+        let sort_depth = |fragment: &Fragment| if fragment.depth.is_nan() { f32::INFINITY } else { depth_mode.distance_key(fragment.depth) };
+        fragments.sort_by(|a, b| sort_depth(b).total_cmp(&sort_depth(a)));
+
+        let mut result_colour = background_colour;
+
+        for fragment in fragments {
             let alpha = fragment.colour.w;
 
             result_colour = fragment.colour.xyz() * alpha + result_colour * (1.0 - alpha);
         }
 
-        self.fragments.clear();
-        self.background = Fragment {
-            colour: background_colour.push(1.0),
-            depth: f32::MAX,
-        };
-
         result_colour
     }
+
+    // McGuire and Bavoil's weighted blended order independent transparency, approximate but sort free
+    fn resolve_weighted_blended(&self, background_colour: Vector3<f32>, depth_mode: DepthMode) -> Vector3<f32> {
+        let background_distance = depth_mode.distance_key(self.background.depth);
+        let mut accumulated = Vector4::zeros();
+        let mut revealage = 1.0;
+
+        for fragment in self.fragments.iter().filter(|fragment| depth_mode.distance_key(fragment.depth) <= background_distance || fragment.depth.is_nan()) {
+            let alpha = fragment.colour.w;
+            // Weighted by distance from the camera, which w is in either depth mode where depth isn't.
+            // NaN depths count as farthest, as in resolve_sorted, which gets the smallest weight.
+            let distance = if fragment.depth.is_nan() { f32::INFINITY } else { fragment.w.abs() };
+            let weight = (10.0 / (1e-5 + (distance / 5.0).powi(2) + (distance / 200.0).powi(6))).clamp(1e-2, 3e3);
+
+            accumulated += fragment.colour.xyz().push(1.0) * alpha * weight;
+            revealage *= 1.0 - alpha;
+        }
+
+        if accumulated.w <= f32::EPSILON { return background_colour }
+
+        accumulated.xyz() / accumulated.w * (1.0 - revealage) + background_colour * revealage
+    }
     
     pub fn get_background(&self) -> &Fragment {
         &self.background
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Vector3, Vector4};
+    use crate::camera::PerspectiveCamera;
+    use crate::renderer::rasterizer::depth::DepthMode;
+    use crate::renderer::rasterizer::TransparencyMode;
+    use super::{Fragment, RenderBufferPixel};
+
+    fn fragment(colour: Vector3<f32>, alpha: f32, depth: f32) -> Fragment {
+        Fragment { colour: colour.push(alpha), depth, w: 1.0 }
+    }
+
+    #[test]
+    fn equal_depths_composite_in_submission_order() {
+        let (red, blue) = (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+
+        for (first, second) in [(red, blue), (blue, red)] {
+            let mut pixel = RenderBufferPixel::new(DepthMode::Standard);
+            pixel.add(fragment(first, 0.5, 0.3), DepthMode::Standard, None);
+            pixel.add(fragment(second, 0.5, 0.3), DepthMode::Standard, None);
+
+            let colour = pixel.resolve(TransparencyMode::Sorted, DepthMode::Standard, Vector3::zeros);
+            assert_eq!(colour, second * 0.5 + first * 0.25);
+        }
+    }
+
+    #[test]
+    fn nan_depths_are_composited_farthest_in_both_modes() {
+        let (red, blue) = (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        let mut pixel = RenderBufferPixel::new(DepthMode::Standard);
+        pixel.add(fragment(red, 0.5, 0.3), DepthMode::Standard, None);
+        pixel.add(fragment(blue, 0.5, f32::NAN), DepthMode::Standard, None);
+
+        let sorted = pixel.resolve(TransparencyMode::Sorted, DepthMode::Standard, Vector3::zeros);
+        assert_eq!(sorted, red * 0.5 + blue * 0.25);
+
+        // Weighted far below the red fragment, but still blended in
+        let blended = pixel.resolve(TransparencyMode::WeightedBlended, DepthMode::Standard, Vector3::zeros);
+        assert!(blended.z > 0.0 && blended.x > blended.z, "{blended:?}");
+        assert!((blended.sum() - 0.75).abs() < 1e-5);
+    }
+
+    #[test]
+    fn weighted_blending_favours_the_nearer_fragment_in_both_depth_modes() {
+        let (red, blue) = (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        let fovy = std::f32::consts::FRAC_PI_2;
+        let projections = [
+            (DepthMode::Standard, PerspectiveCamera::perspective_projection(fovy, 1.0, 0.1, 100.0)),
+            (DepthMode::Reversed, PerspectiveCamera::reversed_z_projection(fovy, 1.0, 0.1, 100.0)),
+        ];
+
+        for (depth_mode, projection) in projections {
+            // Projected from straight ahead of the camera, the far one added first
+            let at_distance = |colour: Vector3<f32>, distance: f32| {
+                let clip = projection * Vector4::new(0.0, 0.0, -distance, 1.0);
+                Fragment { colour: colour.push(0.5), depth: clip.z, w: clip.w }
+            };
+            let mut pixel = RenderBufferPixel::new(depth_mode);
+            pixel.add(at_distance(blue, 50.0), depth_mode, None);
+            pixel.add(at_distance(red, 2.0), depth_mode, None);
+
+            let blended = pixel.resolve(TransparencyMode::WeightedBlended, depth_mode, Vector3::zeros);
+            assert!(blended.x > blended.z * 10.0, "{blended:?} with {depth_mode:?} depth");
+        }
+    }
+
+    #[test]
+    fn fragments_past_the_cap_are_merged() {
+        let (cap, extra) = (4, 6);
+        let layers = (0..cap + extra)
+            .map(|index| {
+                let colour = Vector3::new(index as f32 / 10.0, 1.0 - index as f32 / 10.0, 0.5);
+                fragment(colour, 0.3, 0.9 - index as f32 * 0.05)
+            })
+            .collect::<Vec<_>>();
+
+        // Far to near and near to far, where no later fragment lands between a merged pair so
+        // merging the farthest is exact
+        for far_to_near in [true, false] {
+            let mut limited = RenderBufferPixel::new(DepthMode::Standard);
+            let mut unlimited = RenderBufferPixel::new(DepthMode::Standard);
+
+            for index in 0..layers.len() {
+                let layer = layers[if far_to_near { index } else { layers.len() - 1 - index }];
+                limited.add(layer, DepthMode::Standard, Some(cap));
+                unlimited.add(layer, DepthMode::Standard, None);
+
+                assert!(limited.fragments.len() <= cap, "{} fragments kept after {} added", limited.fragments.len(), index + 1);
+            }
+
+            let resolve = |pixel: &RenderBufferPixel| pixel.resolve(TransparencyMode::Sorted, DepthMode::Standard, Vector3::zeros);
+            assert!((resolve(&limited) - resolve(&unlimited)).norm() < 1e-5, "Added far to near: {far_to_near}");
+        }
+    }
+}
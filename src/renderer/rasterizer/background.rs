@@ -0,0 +1,40 @@
+use nalgebra::{Matrix4, Vector3, Vector4};
+use crate::renderer::rasterizer::cube_map::CubeMap;
+
+// What pixels nothing opaque was drawn to show, transparent fragments still blend over it
+pub enum Background {
+    Solid(Vector3<f32>),
+    // Top colour then bottom colour
+    VerticalGradient(Vector3<f32>, Vector3<f32>),
+    // Sampled along the view ray through each pixel, rebuilt from the view projection in mat4 0
+    Skybox(Box<CubeMap>),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Solid(Vector3::zeros())
+    }
+}
+
+impl Background {
+    pub fn colour_at(&self, x: usize, y: usize, width: usize, height: usize, inverse_view_projection: &Matrix4<f32>) -> Vector3<f32> {
+        match self {
+            Background::Solid(colour) => *colour,
+            Background::VerticalGradient(top, bottom) => {
+                let t = y as f32 / (height.max(2) - 1) as f32;
+                top.lerp(bottom, t)
+            }
+            Background::Skybox(cube_map) => {
+                // Pixel centres sit on integer coordinates, matching the viewport matrix
+                let ndc_x = 2.0 * x as f32 / width as f32 - 1.0;
+                let ndc_y = 1.0 - 2.0 * y as f32 / height as f32;
+
+                let near = inverse_view_projection * Vector4::new(ndc_x, ndc_y, -1.0, 1.0);
+                let far = inverse_view_projection * Vector4::new(ndc_x, ndc_y, 1.0, 1.0);
+                let direction = far.xyz() / far.w - near.xyz() / near.w;
+
+                cube_map.sample(direction).xyz()
+            }
+        }
+    }
+}
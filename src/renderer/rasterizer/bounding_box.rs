@@ -12,24 +12,44 @@ impl BoundingBox {
         Self { min, max }
     }
     
-    pub fn from_triangle(vertex_positions: [Vector2<f32>; 3], bounding_box: BoundingBox) -> Self {
+    // None when the triangle misses the bounding box or it is empty, so callers can skip it
+    // entirely rather than looping over an inverted range. The triangle's bounds are grown by
+    // margin pixels on every side before clamping.
+    pub fn from_triangle(vertex_positions: [Vector2<f32>; 3], bounding_box: BoundingBox, margin: f32) -> Option<Self> {
+        if bounding_box.is_empty() { return None }
+
         let upper_clamp = Vector2::new(bounding_box.max.x as f32 - 1.0, bounding_box.max.y as f32 - 1.0);
         let lower_clamp = Vector2::new(bounding_box.min.x as f32, bounding_box.min.y as f32);
-        let mut bounding_box_min = upper_clamp;
-        let mut bounding_box_max = Vector2::new(0.0_f32, 0.0);
+        // Empty to start with, so a triangle wholly past any side clamps to an inverted box
+        let mut bounding_box_min = Vector2::repeat(f32::INFINITY);
+        let mut bounding_box_max = Vector2::repeat(f32::NEG_INFINITY);
 
         for vertex in &vertex_positions {
-            bounding_box_min.x = bounding_box_min.x.min(vertex.x).max(lower_clamp.x);
-            bounding_box_min.y = bounding_box_min.y.min(vertex.y).max(lower_clamp.y);
+            bounding_box_min.x = bounding_box_min.x.min(vertex.x - margin).max(lower_clamp.x);
+            bounding_box_min.y = bounding_box_min.y.min(vertex.y - margin).max(lower_clamp.y);
 
-            bounding_box_max.x = bounding_box_max.x.max(vertex.x).min(upper_clamp.x);
-            bounding_box_max.y = bounding_box_max.y.max(vertex.y).min(upper_clamp.y);
+            bounding_box_max.x = bounding_box_max.x.max(vertex.x + margin).min(upper_clamp.x);
+            bounding_box_max.y = bounding_box_max.y.max(vertex.y + margin).min(upper_clamp.y);
         }
 
-        Self {
+        // Also catches NaN positions, which fail every comparison
+        if !(bounding_box_min.x <= bounding_box_max.x && bounding_box_min.y <= bounding_box_max.y) { return None }
+
+        Some(Self {
             min: Vector2::new(bounding_box_min.x as usize, bounding_box_min.y as usize),
             max: Vector2::new(bounding_box_max.x as usize, bounding_box_max.y as usize),
-        }
+        })
+    }
+
+    // Both boxes are treated as having exclusive maximums, like the chunk bounds
+    pub fn intersection(&self, other: &BoundingBox) -> Self {
+        let min = self.min.sup(&other.min);
+        let max = self.max.inf(&other.max).sup(&min);
+        Self { min, max }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x >= self.max.x || self.min.y >= self.max.y
     }
 
     pub fn x_iter(&self) -> RangeInclusive<usize> {
@@ -47,4 +67,32 @@ impl BoundingBox {
     pub fn max(&self) -> Vector2<usize> {
         self.max
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+    use super::BoundingBox;
+
+    fn viewport() -> BoundingBox {
+        BoundingBox::new(Vector2::new(0, 0), Vector2::new(16, 16))
+    }
+
+    fn triangle_at(x: f32, y: f32) -> [Vector2<f32>; 3] {
+        [Vector2::new(x, y), Vector2::new(x + 2.0, y), Vector2::new(x, y + 2.0)]
+    }
+
+    #[test]
+    fn off_screen_triangles_have_no_box() {
+        for (x, y) in [(-10.0, 4.0), (4.0, -10.0), (30.0, 4.0), (4.0, 30.0), (-10.0, -10.0)] {
+            assert!(BoundingBox::from_triangle(triangle_at(x, y), viewport(), 0.0).is_none(), "Triangle at ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn on_screen_triangle_is_clamped_to_the_viewport() {
+        let bounding_box = BoundingBox::from_triangle(triangle_at(-1.0, 14.5), viewport(), 0.0).unwrap();
+        assert_eq!(bounding_box.min(), Vector2::new(0, 14));
+        assert_eq!(bounding_box.max(), Vector2::new(1, 15));
+    }
+}
@@ -0,0 +1,204 @@
+use nalgebra::{Vector2, Vector4};
+use rayon::prelude::*;
+use crate::renderer::rasterizer::alpha_buffer::RenderBufferPixel;
+use crate::renderer::rasterizer::bounding_box::BoundingBox;
+use crate::renderer::rasterizer::framebuffer::Framebuffer;
+
+// Splits the render buffer into horizontal bands of rows, one per thread
+pub struct ChunkLayout {
+    width: usize,
+    height: usize,
+    rows_per_chunk: usize,
+    num_chunks: usize,
+    scissor: BoundingBox,
+}
+
+impl ChunkLayout {
+    // Chunks only cover the part of their rows inside the scissor box
+    pub fn new(width: usize, height: usize, scissor: BoundingBox) -> Self {
+        let num_threads = rayon::current_num_threads();
+
+        // Round up so the rows are fully covered, the last chunk takes whatever remains
+        let rows_per_chunk = height.div_ceil(num_threads).max(1);
+
+        Self {
+            width,
+            height,
+            rows_per_chunk,
+            num_chunks: height.div_ceil(rows_per_chunk),
+            scissor,
+        }
+    }
+
+    // Bins items into the row chunks they overlap so each thread only visits its own
+    pub fn bin<'a, T>(&self, items: &'a [T], y_range: impl Fn(&T) -> (f32, f32)) -> Vec<Vec<&'a T>> {
+        let mut bins = vec![Vec::new(); self.num_chunks];
+        if self.num_chunks == 0 { return bins }
+
+        for item in items {
+            let (min_y, max_y) = y_range(item);
+            let first_chunk = (min_y.max(0.0) as usize / self.rows_per_chunk).min(self.num_chunks - 1);
+            let last_chunk = (max_y.max(0.0) as usize / self.rows_per_chunk).min(self.num_chunks - 1);
+
+            for bin in &mut bins[first_chunk..=last_chunk] {
+                bin.push(item);
+            }
+        }
+
+        bins
+    }
+
+    pub fn for_each_chunk(
+        &self,
+        framebuffer: &mut Framebuffer,
+        draw: impl Fn(&mut RasterChunk) + Sync + Send,
+    ) {
+        let chunk_len = self.width * self.rows_per_chunk;
+        if chunk_len == 0 { return }
+
+        let (render_buffer, stencil_buffer, targets) = framebuffer.buffers_mut();
+
+        // Each chunk takes its rows of every target
+        let mut target_chunks = (0..self.num_chunks).map(|_| Vec::with_capacity(targets.len())).collect::<Vec<_>>();
+        for target in targets {
+            for (chunk_targets, rows) in target_chunks.iter_mut().zip(target.chunks_mut(chunk_len)) {
+                chunk_targets.push(rows);
+            }
+        }
+
+        render_buffer.par_chunks_mut(chunk_len)
+            .zip(stencil_buffer.par_chunks_mut(chunk_len))
+            .zip(target_chunks)
+            .enumerate()
+            .for_each(|(index, ((pixels, stencils), targets))| {
+                let start = index * self.rows_per_chunk;
+                let end = (start + self.rows_per_chunk).min(self.height);
+
+                let rows = BoundingBox::new(Vector2::new(0, start), Vector2::new(self.width, end));
+                let bounding_box = rows.intersection(&self.scissor);
+                if bounding_box.is_empty() { return }
+
+                let mut chunk = RasterChunk {
+                    index,
+                    bounding_box,
+                    start_index: index * chunk_len,
+                    width: self.width,
+                    pixels,
+                    stencils,
+                    targets,
+                };
+                draw(&mut chunk);
+            });
+    }
+}
+
+pub struct RasterChunk<'a> {
+    pub index: usize,
+    // Exclusive upper bound, from_triangle clamps to the last row and column inside it
+    pub bounding_box: BoundingBox,
+    start_index: usize,
+    width: usize,
+    pixels: &'a mut [RenderBufferPixel],
+    stencils: &'a mut [u8],
+    targets: Vec<&'a mut [Vector4<f32>]>,
+}
+
+pub struct PixelTarget<'a, 'b> {
+    pub pixel: &'a mut RenderBufferPixel,
+    pub stencil: &'a mut u8,
+    pub targets: TargetWriter<'a, 'b>,
+}
+
+// The pixel's entry in each of the framebuffer's extra targets
+pub struct TargetWriter<'a, 'b> {
+    targets: &'a mut [&'b mut [Vector4<f32>]],
+    offset: usize,
+}
+
+impl TargetWriter<'_, '_> {
+    // Values past the framebuffer's number of targets are dropped
+    pub fn write(&mut self, values: &[Vector4<f32>]) {
+        for (target, value) in self.targets.iter_mut().zip(values) {
+            target[self.offset] = *value;
+        }
+    }
+}
+
+impl<'a> RasterChunk<'a> {
+    pub fn pixel_mut(&mut self, x: usize, y: usize) -> PixelTarget<'_, 'a> {
+        // An x past the row would land on the next row's pixel rather than fail the range check
+        debug_assert!(x < self.width, "Pixel ({x}, {y}) is past the end of a {} pixel row", self.width);
+        let index = x + y * self.width;
+        debug_assert!(
+            (self.start_index..self.start_index + self.pixels.len()).contains(&index),
+            "Pixel ({x}, {y}) is outside the chunk starting at index {}", self.start_index,
+        );
+        let offset = index - self.start_index;
+        PixelTarget {
+            pixel: &mut self.pixels[offset],
+            stencil: &mut self.stencils[offset],
+            targets: TargetWriter { targets: &mut self.targets, offset },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use rayon::ThreadPoolBuilder;
+    use crate::renderer::rasterizer::RasterOptions;
+    use crate::renderer::rasterizer::Rasterizer;
+    use crate::renderer::rasterizer::test_support::{drawn_pixels, full_screen_triangle, SolidShader};
+    // Only the range checks' tests, which need debug assertions
+    #[cfg(debug_assertions)]
+    use {nalgebra::Vector2, crate::renderer::rasterizer::{alpha_buffer::RenderBufferPixel, bounding_box::BoundingBox, depth::DepthMode}, super::RasterChunk};
+
+    // 721 rows split evenly between neither thread count, leaving a shorter last chunk
+    #[test]
+    fn full_screen_triangle_leaves_no_row_blank() {
+        let (width, height) = (1281, 721);
+        let full_screen = full_screen_triangle(0.0);
+
+        for threads in [4, 16] {
+            let thread_pool = ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let options = RasterOptions { thread_pool: Some(Arc::new(thread_pool)), ..Default::default() };
+            let mut rasterizer = Rasterizer::new(width, height, options);
+            rasterizer.draw_mesh(&full_screen, &SolidShader::red()).unwrap();
+
+            let drawn = drawn_pixels(&rasterizer);
+            for (y, row) in drawn.chunks(width).enumerate() {
+                assert!(row.iter().all(|&drawn| drawn), "Row {y} has background pixels with {threads} threads");
+            }
+        }
+    }
+
+    // The second row of a 4 pixel wide buffer
+    #[cfg(debug_assertions)]
+    fn second_row<'a>(pixels: &'a mut [RenderBufferPixel], stencils: &'a mut [u8]) -> RasterChunk<'a> {
+        RasterChunk {
+            index: 1,
+            bounding_box: BoundingBox::new(Vector2::new(0, 1), Vector2::new(4, 2)),
+            start_index: 4,
+            width: 4,
+            pixels,
+            stencils,
+            targets: Vec::new(),
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "past the end of a 4 pixel row")]
+    fn pixel_past_the_row_panics() {
+        let (mut pixels, mut stencils) = ((0..4).map(|_| RenderBufferPixel::new(DepthMode::Standard)).collect::<Vec<_>>(), [0; 4]);
+        second_row(&mut pixels, &mut stencils).pixel_mut(4, 1);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "outside the chunk")]
+    fn pixel_outside_the_chunk_panics() {
+        let (mut pixels, mut stencils) = ((0..4).map(|_| RenderBufferPixel::new(DepthMode::Standard)).collect::<Vec<_>>(), [0; 4]);
+        second_row(&mut pixels, &mut stencils).pixel_mut(0, 2);
+    }
+}
@@ -0,0 +1,205 @@
+use nalgebra::Vector4;
+use crate::renderer::rasterizer::depth::DepthMode;
+use crate::shader::VertexShaderOutputVariables;
+
+type ClipPlane = fn(&Vector4<f32>) -> f32;
+
+// Signed distance to each frustum plane in clip space, negative is outside. Depth runs from -w
+// to w for Standard and from 0 at the far plane to w at the near plane for Reversed.
+fn clip_planes(depth_mode: DepthMode) -> [ClipPlane; 6] {
+    let min_depth_plane: ClipPlane = match depth_mode {
+        DepthMode::Standard => |position| position.w + position.z,
+        DepthMode::Reversed => |position| position.z,
+    };
+
+    [
+        |position| position.w + position.x,
+        |position| position.w - position.x,
+        |position| position.w + position.y,
+        |position| position.w - position.y,
+        min_depth_plane,
+        |position| position.w - position.z,
+    ]
+}
+
+// Lines only need the near and far planes before the perspective divide
+fn depth_clip_planes(depth_mode: DepthMode) -> [ClipPlane; 2] {
+    let planes = clip_planes(depth_mode);
+    [planes[4], planes[5]]
+}
+
+pub enum ClipResult {
+    Inside,
+    Outside,
+    Clipped(Vec<VertexShaderOutputVariables>),
+}
+
+pub fn clip_triangle(vertex_outputs: &[&VertexShaderOutputVariables; 3], depth_mode: DepthMode) -> ClipResult {
+    let planes = clip_planes(depth_mode);
+
+    let mut inside = true;
+    for plane in planes {
+        let distances = vertex_outputs.map(|output| plane(&output.position));
+
+        if distances.iter().all(|distance| *distance < 0.0) { return ClipResult::Outside }
+        if distances.iter().any(|distance| *distance < 0.0) { inside = false }
+    }
+
+    if inside { return ClipResult::Inside }
+
+    let mut polygon = vertex_outputs.iter()
+        .map(|output| (*output).clone())
+        .collect::<Vec<_>>();
+
+    for plane in planes {
+        polygon = clip_polygon(&polygon, plane);
+        if polygon.len() < 3 { return ClipResult::Outside }
+    }
+
+    // Every triangle of the clipped polygon keeps the original first vertex's flat varyings,
+    // even where that vertex was clipped away
+    for output in &mut polygon {
+        output.copy_flat_from(vertex_outputs[0]);
+    }
+
+    ClipResult::Clipped(polygon)
+}
+
+pub fn clip_line(vertex_outputs: &[&VertexShaderOutputVariables; 2], depth_mode: DepthMode) -> ClipResult {
+    let [start, end] = vertex_outputs;
+    let mut t_start = 0.0_f32;
+    let mut t_end = 1.0_f32;
+
+    for plane in depth_clip_planes(depth_mode) {
+        let start_distance = plane(&start.position);
+        let end_distance = plane(&end.position);
+
+        if start_distance < 0.0 && end_distance < 0.0 { return ClipResult::Outside }
+
+        let t = start_distance / (start_distance - end_distance);
+        if start_distance < 0.0 {
+            t_start = t_start.max(t);
+        } else if end_distance < 0.0 {
+            t_end = t_end.min(t);
+        }
+    }
+
+    if t_start > t_end { return ClipResult::Outside }
+    if t_start == 0.0 && t_end == 1.0 { return ClipResult::Inside }
+
+    ClipResult::Clipped(vec![start.lerp(end, t_start), start.lerp(end, t_end)])
+}
+
+// Conservative, only rejects when every corner is outside the same plane
+pub fn box_outside(corners: &[Vector4<f32>], depth_mode: DepthMode) -> bool {
+    clip_planes(depth_mode).iter().any(|plane| corners.iter().all(|corner| plane(corner) < 0.0))
+}
+
+pub fn point_inside(position: &Vector4<f32>, depth_mode: DepthMode) -> bool {
+    clip_planes(depth_mode).iter().all(|plane| plane(position) >= 0.0)
+}
+
+// Triangulates a convex clipped polygon as a fan around its first vertex, keeping the winding
+pub fn triangulate(polygon: &[VertexShaderOutputVariables]) -> impl Iterator<Item = [&VertexShaderOutputVariables; 3]> {
+    (1..polygon.len() - 1).map(|i| [&polygon[0], &polygon[i], &polygon[i + 1]])
+}
+
+fn clip_polygon(polygon: &[VertexShaderOutputVariables], plane: ClipPlane) -> Vec<VertexShaderOutputVariables> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+
+    for (i, current) in polygon.iter().enumerate() {
+        let next = &polygon[(i + 1) % polygon.len()];
+        let current_distance = plane(&current.position);
+        let next_distance = plane(&next.position);
+
+        if current_distance >= 0.0 {
+            clipped.push(current.clone());
+        }
+
+        if (current_distance >= 0.0) != (next_distance >= 0.0) {
+            let t = current_distance / (current_distance - next_distance);
+            clipped.push(current.lerp(next, t));
+        }
+    }
+
+    clipped
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use crate::renderer::rasterizer::depth::DepthMode;
+    use crate::renderer::rasterizer::{RasterOptions, Rasterizer};
+    use crate::renderer::rasterizer::test_support::{full_screen_triangle, SolidShader};
+    use crate::shader::VertexShaderOutputVariables;
+    use super::{clip_triangle, ClipPlane, ClipResult};
+
+    // Clips a triangle with only its last vertex outside plane, which should cut that corner off
+    // leaving a quad with its two new vertices on the plane
+    fn assert_corner_clipped(positions: [Vector4<f32>; 3], depth_mode: DepthMode, plane: ClipPlane) {
+        let outputs = positions.map(VertexShaderOutputVariables::new);
+
+        let ClipResult::Clipped(polygon) = clip_triangle(&outputs.each_ref(), depth_mode) else { panic!("{positions:?} crosses the plane") };
+        assert_eq!(polygon.len(), 4);
+        for output in &polygon {
+            assert!(plane(&output.position) >= -1e-6, "{:?} is outside the plane", output.position);
+        }
+        let on_plane = polygon.iter().filter(|output| plane(&output.position).abs() < 1e-6).count();
+        assert_eq!(on_plane, 2);
+    }
+
+    #[test]
+    fn vertex_left_of_the_view_is_clipped_away() {
+        let positions = [Vector4::new(0.5, -0.5, 0.0, 1.0), Vector4::new(0.5, 0.5, 0.0, 1.0), Vector4::new(-2.0, 0.0, 0.0, 1.0)];
+        assert_corner_clipped(positions, DepthMode::Standard, |position| position.w + position.x);
+    }
+
+    #[test]
+    fn vertex_right_of_the_view_is_clipped_away() {
+        let positions = [Vector4::new(-0.5, -0.5, 0.0, 1.0), Vector4::new(-0.5, 0.5, 0.0, 1.0), Vector4::new(2.0, 0.0, 0.0, 1.0)];
+        assert_corner_clipped(positions, DepthMode::Standard, |position| position.w - position.x);
+    }
+
+    #[test]
+    fn vertex_below_the_view_is_clipped_away() {
+        let positions = [Vector4::new(-0.5, 0.5, 0.0, 1.0), Vector4::new(0.5, 0.5, 0.0, 1.0), Vector4::new(0.0, -2.0, 0.0, 1.0)];
+        assert_corner_clipped(positions, DepthMode::Standard, |position| position.w + position.y);
+    }
+
+    #[test]
+    fn vertex_above_the_view_is_clipped_away() {
+        let positions = [Vector4::new(-0.5, -0.5, 0.0, 1.0), Vector4::new(0.5, -0.5, 0.0, 1.0), Vector4::new(0.0, 2.0, 0.0, 1.0)];
+        assert_corner_clipped(positions, DepthMode::Standard, |position| position.w - position.y);
+    }
+
+    #[test]
+    fn vertex_behind_the_near_plane_is_clipped_away() {
+        let positions = [Vector4::new(-0.5, -0.5, 0.0, 1.0), Vector4::new(0.5, -0.5, 0.0, 1.0), Vector4::new(0.0, 0.5, -2.0, 1.0)];
+        assert_corner_clipped(positions, DepthMode::Standard, |position| position.w + position.z);
+    }
+
+    #[test]
+    fn vertex_past_the_far_plane_is_clipped_away() {
+        let positions = [Vector4::new(-0.5, -0.5, 0.0, 1.0), Vector4::new(0.5, -0.5, 0.0, 1.0), Vector4::new(0.0, 0.5, 2.0, 1.0)];
+        assert_corner_clipped(positions, DepthMode::Standard, |position| position.w - position.z);
+    }
+
+    #[test]
+    fn reversed_depth_clips_at_its_own_far_plane() {
+        // Inside Standard's -w to w, but partly past Reversed's far plane at z = 0
+        let positions = [Vector4::new(-0.5, -0.5, 0.5, 1.0), Vector4::new(0.5, -0.5, 0.5, 1.0), Vector4::new(0.0, 0.5, -0.5, 1.0)];
+
+        let outputs = positions.map(VertexShaderOutputVariables::new);
+        assert!(matches!(clip_triangle(&outputs.each_ref(), DepthMode::Standard), ClipResult::Inside));
+        assert_corner_clipped(positions, DepthMode::Reversed, |position| position.z);
+    }
+
+    #[test]
+    fn triangle_behind_the_near_plane_shades_nothing() {
+        let mut rasterizer = Rasterizer::new(8, 8, RasterOptions { collect_stats: true, ..Default::default() });
+        rasterizer.draw_mesh(&full_screen_triangle(-1.5), &SolidShader::red()).unwrap();
+
+        assert_eq!(rasterizer.stats().fragments_shaded, 0);
+        assert_eq!(rasterizer.stats().triangles_clipped, 1);
+    }
+}
@@ -0,0 +1,38 @@
+use nalgebra::{Vector3, Vector4};
+use crate::renderer::rasterizer::texture2d::Texture2D;
+
+// Faces in OpenGL order: +x, -x, +y, -y, +z, -z, each image stored top row first
+pub struct CubeMap {
+    faces: [Texture2D; 6],
+}
+
+impl CubeMap {
+    pub fn new(faces: [Texture2D; 6]) -> Self {
+        Self { faces }
+    }
+
+    pub fn sample(&self, direction: Vector3<f32>) -> Vector4<f32> {
+        let abs = direction.abs();
+
+        // Pick the face by the dominant axis, then project the other two axes onto it
+        let (face, major, s, t) = if abs.x >= abs.y && abs.x >= abs.z {
+            if direction.x > 0.0 { (0, abs.x, -direction.z, -direction.y) } else { (1, abs.x, direction.z, -direction.y) }
+        } else if abs.y >= abs.z {
+            if direction.y > 0.0 { (2, abs.y, direction.x, direction.z) } else { (3, abs.y, direction.x, -direction.z) }
+        } else if direction.z > 0.0 {
+            (4, abs.z, direction.x, -direction.y)
+        } else {
+            (5, abs.z, -direction.x, -direction.y)
+        };
+
+        if major == 0.0 { return self.faces[4].sample(0.5, 0.5) }
+
+        let u = (s / major + 1.0) / 2.0;
+        let v = (t / major + 1.0) / 2.0;
+
+        // Cube map t runs from the top of the image, Texture2D::sample's v runs from the bottom
+        // unless the face has flip_v off. Coordinates on an edge are clamped to that face by the sampler
+        let face = &self.faces[face];
+        face.sample(u, if face.flip_v() { 1.0 - v } else { v })
+    }
+}
@@ -0,0 +1,54 @@
+// How stored depths are cleared and compared, the projection must match the mode.
+// Reversed pairs with PerspectiveCamera::reversed_z_projection and keeps far depths
+// near zero where floats are densest, which reduces z-fighting on distant geometry.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DepthMode {
+    // Cleared to f32::MAX, nearer fragments have smaller depths and pass with Less
+    #[default]
+    Standard,
+    // Cleared to 0, nearer fragments have larger depths and pass with Greater.
+    // Anything at or past the far plane fails the test against the clear value.
+    Reversed,
+}
+
+impl DepthMode {
+    pub fn clear_depth(self) -> f32 {
+        match self {
+            DepthMode::Standard => f32::MAX,
+            DepthMode::Reversed => 0.0,
+        }
+    }
+
+    // Fails the depth test when depth is no nearer than stored, NaN depths are never occluded
+    pub fn is_occluded(self, depth: f32, stored: f32) -> bool {
+        match self {
+            DepthMode::Standard => depth >= stored,
+            DepthMode::Reversed => depth <= stored,
+        }
+    }
+
+    // Whether a clip space depth with the given clip w is between the near and far planes,
+    // -w to w for Standard and 0 to w for Reversed
+    pub fn in_range(self, depth: f32, w: f32) -> bool {
+        match self {
+            DepthMode::Standard => (-w..=w).contains(&depth),
+            DepthMode::Reversed => (0.0..=w).contains(&depth),
+        }
+    }
+
+    // Moves a depth amount towards the near plane
+    pub fn nearer_by(self, depth: f32, amount: f32) -> f32 {
+        match self {
+            DepthMode::Standard => depth - amount,
+            DepthMode::Reversed => depth + amount,
+        }
+    }
+
+    // Maps a depth so larger is always farther, for sorting and normalizing
+    pub fn distance_key(self, depth: f32) -> f32 {
+        match self {
+            DepthMode::Standard => depth,
+            DepthMode::Reversed => -depth,
+        }
+    }
+}
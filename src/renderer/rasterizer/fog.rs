@@ -0,0 +1,72 @@
+use nalgebra::Vector3;
+use crate::renderer::rasterizer::depth::DepthMode;
+
+#[derive(Copy, Clone)]
+pub enum FogMode {
+    // No fog before start, fully fogged past end, in view space distance
+    Linear { start: f32, end: f32 },
+    Exponential { density: f32 },
+    ExponentialSquared { density: f32 },
+}
+
+// Blends each pixel toward the fog colour by the distance to its nearest opaque fragment.
+// z_near and z_far must match the projection so clip space depth can be linearized.
+#[derive(Copy, Clone)]
+pub struct Fog {
+    pub colour: Vector3<f32>,
+    pub mode: FogMode,
+    pub z_near: f32,
+    pub z_far: f32,
+    // Whether pixels nothing opaque was drawn to are fully fogged or keep the background
+    pub fog_background: bool,
+}
+
+impl Fog {
+    pub fn apply(&self, colour: Vector3<f32>, depth: f32, depth_mode: DepthMode) -> Vector3<f32> {
+        if depth == depth_mode.clear_depth() {
+            return if self.fog_background { self.colour } else { colour };
+        }
+
+        let amount = self.amount(self.linearize(depth, depth_mode)).clamp(0.0, 1.0);
+        colour.lerp(&self.colour, amount)
+    }
+
+    // Depths are clip space z, which a perspective projection makes affine in view distance
+    fn linearize(&self, depth: f32, depth_mode: DepthMode) -> f32 {
+        let (near, far) = (self.z_near, self.z_far);
+        let (scale, offset) = match depth_mode {
+            DepthMode::Standard => ((far + near) / (far - near), -2.0 * far * near / (far - near)),
+            DepthMode::Reversed => (-near / (far - near), far * near / (far - near)),
+        };
+        (depth - offset) / scale
+    }
+
+    fn amount(&self, distance: f32) -> f32 {
+        match self.mode {
+            FogMode::Linear { start, end } => (distance - start) / (end - start).max(f32::EPSILON),
+            FogMode::Exponential { density } => 1.0 - (-density * distance).exp(),
+            FogMode::ExponentialSquared { density } => 1.0 - (-(density * distance).powi(2)).exp(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Vector3, Vector4};
+    use crate::camera::PerspectiveCamera;
+    use crate::renderer::rasterizer::depth::DepthMode;
+    use super::{Fog, FogMode};
+
+    #[test]
+    fn linear_fog_thickens_with_distance() {
+        let (z_near, z_far) = (1.0, 100.0);
+        let fog = Fog { colour: Vector3::repeat(1.0), mode: FogMode::Linear { start: 2.0, end: 10.0 }, z_near, z_far, fog_background: false };
+        let projection = PerspectiveCamera::perspective_projection(1.0, 1.0, z_near, z_far);
+
+        for (distance, expected) in [(4.0, 0.25), (8.0, 0.75)] {
+            let depth = (projection * Vector4::new(0.0, 0.0, -distance, 1.0)).z;
+            let fogged = fog.apply(Vector3::zeros(), depth, DepthMode::Standard);
+            assert!((fogged - Vector3::repeat(expected)).norm() < 1e-4, "{fogged:?} at {distance}");
+        }
+    }
+}
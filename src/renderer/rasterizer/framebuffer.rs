@@ -0,0 +1,253 @@
+use nalgebra::{Vector3, Vector4};
+use crate::renderer::rasterizer::alpha_buffer::RenderBufferPixel;
+use crate::renderer::rasterizer::depth::DepthMode;
+use crate::renderer::rasterizer::fog::Fog;
+use crate::renderer::rasterizer::TransparencyMode;
+
+// One value per pixel, row by row
+type RenderTarget = Vec<Vector4<f32>>;
+
+// Everything the rasterizer draws into, every buffer always holds width * height entries
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<RenderBufferPixel>,
+    stencils: Vec<u8>,
+    // Extra outputs per pixel from FragmentOutput::Targets, only written by opaque fragments
+    targets: Vec<RenderTarget>,
+    // The mode the pixels were last cleared with, resize keeps it
+    depth_mode: DepthMode,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize, depth_mode: DepthMode, target_count: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: Self::build_pixels(width, height, depth_mode),
+            stencils: vec![0; width * height],
+            targets: vec![vec![Vector4::zeros(); width * height]; target_count],
+            depth_mode,
+        }
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.pixels = Self::build_pixels(width, height, self.depth_mode);
+        self.stencils = vec![0; width * height];
+        for target in &mut self.targets {
+            *target = vec![Vector4::zeros(); width * height];
+        }
+    }
+
+    fn build_pixels(width: usize, height: usize, depth_mode: DepthMode) -> Vec<RenderBufferPixel> {
+        let mut pixels = Vec::with_capacity(width * height);
+        for _ in 0..width * height {
+            pixels.push(RenderBufferPixel::new(depth_mode));
+        }
+        pixels
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Targets are cleared to zero, added or dropped to match target_count
+    pub fn clear(&mut self, depth_mode: DepthMode, target_count: usize) {
+        self.targets.truncate(target_count);
+        for target in &mut self.targets {
+            target.fill(Vector4::zeros());
+        }
+        let size = self.width * self.height;
+        self.targets.resize_with(target_count, || vec![Vector4::zeros(); size]);
+
+        self.depth_mode = depth_mode;
+        for pixel in &mut self.pixels {
+            pixel.clear(depth_mode);
+        }
+        self.stencils.fill(0);
+    }
+
+    // background_at gives the colour for pixels nothing opaque was drawn to, from their x and y.
+    // Colours are multiplied by exposure, then with output_srgb taken as linear and sRGB encoded,
+    // before quantizing.
+    pub fn resolve_into(
+        &self,
+        buffer: &mut [u32],
+        transparency: TransparencyMode,
+        fog: Option<&Fog>,
+        exposure: f32,
+        output_srgb: bool,
+        background_at: impl Fn(usize, usize) -> Vector3<f32>,
+    ) {
+        if buffer.len() != self.width * self.height {
+            panic!("Buffer length does not match image size");
+        }
+
+        for (index, output) in buffer.iter_mut().enumerate() {
+            let colour = self.resolve_pixel(index, transparency, fog, exposure, &background_at);
+            let colour = if output_srgb { colour.map(linear_to_srgb) } else { colour };
+            *output = Self::convert_colour_to_u32(colour);
+        }
+    }
+
+    // Like resolve_into but keeping the colours as floats, unclamped
+    pub fn resolve_linear_into(
+        &self,
+        buffer: &mut [Vector3<f32>],
+        transparency: TransparencyMode,
+        fog: Option<&Fog>,
+        exposure: f32,
+        background_at: impl Fn(usize, usize) -> Vector3<f32>,
+    ) {
+        if buffer.len() != self.width * self.height {
+            panic!("Buffer length does not match image size");
+        }
+
+        for (index, output) in buffer.iter_mut().enumerate() {
+            *output = self.resolve_pixel(index, transparency, fog, exposure, &background_at);
+        }
+    }
+
+    // The resolved colour before it's quantized, None outside the framebuffer
+    pub fn pixel_at(
+        &self,
+        x: usize,
+        y: usize,
+        transparency: TransparencyMode,
+        fog: Option<&Fog>,
+        exposure: f32,
+        background_at: impl Fn(usize, usize) -> Vector3<f32>,
+    ) -> Option<Vector3<f32>> {
+        if x >= self.width || y >= self.height { return None }
+
+        Some(self.resolve_pixel(y * self.width + x, transparency, fog, exposure, &background_at))
+    }
+
+    fn resolve_pixel(
+        &self,
+        index: usize,
+        transparency: TransparencyMode,
+        fog: Option<&Fog>,
+        exposure: f32,
+        background_at: &impl Fn(usize, usize) -> Vector3<f32>,
+    ) -> Vector3<f32> {
+        let pixel = &self.pixels[index];
+        let mut colour = pixel.resolve(transparency, self.depth_mode, || background_at(index % self.width, index / self.width));
+        if let Some(fog) = fog {
+            colour = fog.apply(colour, pixel.get_background().depth, self.depth_mode);
+        }
+        colour * exposure
+    }
+
+    // Opaque depth per pixel in the depth mode's distance order, so larger is always farther,
+    // f32::MAX where nothing opaque was drawn
+    pub fn distances(&self) -> Vec<f32> {
+        let clear_depth = self.depth_mode.clear_depth();
+        self.pixels.iter()
+            .map(|pixel| pixel.get_background().depth)
+            .map(|depth| if depth == clear_depth { f32::MAX } else { self.depth_mode.distance_key(depth) })
+            .collect()
+    }
+
+    // Opaque depth normalized to the nearest and farthest values drawn this frame,
+    // near is black and pixels nothing was drawn to are white
+    pub fn depth_image(&self) -> Vec<u8> {
+        let clear_depth = self.depth_mode.clear_depth();
+        let distances = self.pixels.iter()
+            .map(|pixel| pixel.get_background().depth)
+            .map(|depth| (depth != clear_depth).then(|| self.depth_mode.distance_key(depth)))
+            .collect::<Vec<_>>();
+
+        let drawn_distances = distances.iter().copied().flatten();
+        let (near, far) = drawn_distances.fold((f32::MAX, f32::MIN), |(near, far), distance| (near.min(distance), far.max(distance)));
+        let range = (far - near).max(f32::EPSILON);
+
+        distances.into_iter()
+            .map(|distance| match distance {
+                Some(distance) => (((distance - near) / range).clamp(0.0, 1.0) * 255.0) as u8,
+                None => u8::MAX,
+            })
+            .collect()
+    }
+
+    // Row by row like the colours, None past the number of targets
+    pub fn target(&self, index: usize) -> Option<&[Vector4<f32>]> {
+        self.targets.get(index).map(Vec::as_slice)
+    }
+
+    pub fn target_count(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub(crate) fn buffers_mut(&mut self) -> (&mut [RenderBufferPixel], &mut [u8], &mut [RenderTarget]) {
+        (&mut self.pixels, &mut self.stencils, &mut self.targets)
+    }
+
+    pub(crate) fn convert_colour_to_u32(colour: Vector3<f32>) -> u32 {
+        let r = (colour.x * 255.0) as u8 as u32;
+        let g = (colour.y * 255.0) as u8 as u32;
+        let b = (colour.z * 255.0) as u8 as u32;
+        (r << 16) | (g << 8) | b
+    }
+}
+
+// The sRGB transfer function, clamping to 0.0 to 1.0 first
+pub fn linear_to_srgb(channel: f32) -> f32 {
+    let channel = channel.clamp(0.0, 1.0);
+    if channel <= 0.0031308 {
+        channel * 12.92
+    } else if channel < 1.0 {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    } else {
+        // The curve lands just under 1 here, which the 8 bit conversion would truncate to 254
+        1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use crate::renderer::rasterizer::{RasterOptions, Rasterizer};
+    use crate::renderer::rasterizer::test_support::{full_screen_triangle, resolved_pixel, SolidShader};
+    use super::linear_to_srgb;
+
+    // A full screen triangle of colour, resolved to 8 bits
+    fn resolved(colour: Vector3<f32>, options: RasterOptions) -> u32 {
+        let mut rasterizer = Rasterizer::new(2, 2, options);
+        rasterizer.draw_mesh(&full_screen_triangle(0.0), &SolidShader(colour.push(1.0))).unwrap();
+        resolved_pixel(&rasterizer, 0, 0)
+    }
+
+    #[test]
+    fn srgb_keeps_black_and_white() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert_eq!(linear_to_srgb(1.0), 1.0);
+        assert_eq!((linear_to_srgb(-1.0), linear_to_srgb(2.0)), (0.0, 1.0));
+
+        let options = || RasterOptions { output_srgb: true, ..Default::default() };
+        assert_eq!(resolved(Vector3::zeros(), options()), 0x000000);
+        assert_eq!(resolved(Vector3::repeat(1.0), options()), 0xffffff);
+    }
+
+    #[test]
+    fn srgb_brightens_mid_grey() {
+        assert!((linear_to_srgb(0.5) - 0.7354).abs() < 1e-3);
+
+        let options = RasterOptions { output_srgb: true, ..Default::default() };
+        assert_eq!(resolved(Vector3::repeat(0.5), options), 0xbbbbbb);
+    }
+
+    #[test]
+    fn exposure_of_two_doubles_colours() {
+        let options = || RasterOptions { exposure: 2.0, ..Default::default() };
+        assert_eq!(resolved(Vector3::new(0.25, 0.125, 0.0), options()), 0x7f3f00);
+        // Clamped in 8 bit output
+        assert_eq!(resolved(Vector3::repeat(0.75), options()), 0xffffff);
+    }
+}
@@ -1,49 +1,200 @@
-use crate::mesh::{Face, Mesh};
-use crate::shader::{FragmentShaderInputVariables, Shader, VertexShaderInputVariables, VertexShaderOutputVariables};
+use crate::material::Material;
+use crate::mesh::{Face, IndexedMesh, Mesh, Quad, QuadMesh, Vertex};
+use crate::shader::{FragmentOutput, FragmentShaderInputVariables, InstancedShader, Shader, VertexShaderInputVariables, VertexShaderOutputVariables};
+use std::ops::Range;
+use std::sync::Arc;
 use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
-use rayon::prelude::*;
-use crate::renderer::rasterizer::alpha_buffer::{Fragment, RenderBufferPixel};
+use rayon::ThreadPool;
+use crate::renderer::rasterizer::alpha_buffer::Fragment;
+use crate::renderer::rasterizer::background::Background;
 use crate::renderer::rasterizer::bounding_box::BoundingBox;
-use crate::renderer::rasterizer::storage::Storage;
+use crate::renderer::rasterizer::chunk::{ChunkLayout, PixelTarget, RasterChunk};
+use crate::renderer::rasterizer::clipper::ClipResult;
+use crate::renderer::rasterizer::depth::DepthMode;
+use crate::renderer::rasterizer::fog::Fog;
+use crate::renderer::rasterizer::framebuffer::Framebuffer;
+use crate::renderer::rasterizer::screen_line::ScreenLine;
+use crate::renderer::rasterizer::screen_triangle::ScreenTriangle;
+use crate::renderer::rasterizer::stats::{FragmentCounters, RasterStats};
+use crate::renderer::rasterizer::stencil::StencilOptions;
+use crate::renderer::rasterizer::storage::{BindingError, Storage};
+use crate::renderer::scene::{Geometry, Scene};
 
 pub mod texture2d;
+pub mod background;
+pub mod depth;
+pub mod fog;
+pub mod cube_map;
+pub mod framebuffer;
 mod bounding_box;
+mod chunk;
+mod clipper;
+mod screen_line;
+mod screen_triangle;
+pub mod stencil;
+pub mod stats;
 pub mod storage;
 mod alpha_buffer;
+#[cfg(test)]
+pub(crate) mod test_support;
 
 pub struct RasterOptions {
     pub cull_backfaces: bool,
-    pub background_colour: Vector3<f32>,
+    pub polygon_mode: PolygonMode,
+    pub background: Background,
+    pub stencil: Option<StencilOptions>,
+    pub alpha_cutoff: Option<f32>,
+    pub transparency: TransparencyMode,
+    pub fog: Option<Fog>,
+    // Takes effect from the next clear, the projection has to be switched to match
+    pub depth_mode: DepthMode,
+    // Draws each mesh's triangles nearest first so the depth test rejects occluded fragments
+    // before they're shaded. Fragments reach the buffers out of submission order, so leave it
+    // off when stencil or coplanar transparent effects depend on draw order.
+    pub sort_front_to_back: bool,
+    // Shades every pixel a triangle overlaps any part of rather than only those whose centre
+    // it covers, so thin and sub-pixel triangles don't drop out. Overlapping pixels outside
+    // the triangle are shaded with their attributes clamped to its nearest edge.
+    pub conservative: bool,
+    // Discards fragments whose depth lands outside the near to far range, on by default.
+    // Clipping keeps nearly all of them in, this catches what rounding and conservative
+    // coverage let through, which would otherwise write from behind the camera.
+    pub reject_outside_depth_range: bool,
+    // Polygon offset, moving triangles' fragments towards the camera in either depth mode by
+    // depth_bias plus slope_scaled_depth_bias times the triangle's steepest depth change per
+    // pixel, both in normalized device depth, which spans 2 for Standard and 1 for Reversed.
+    // Lets decals win the depth test against the surface they're coplanar with.
+    pub depth_bias: f32,
+    pub slope_scaled_depth_bias: f32,
+    // Per pixel outputs kept alongside the colour, for shaders returning FragmentOutput::Targets
+    // to fill a G-buffer. Takes effect from the next clear, read back with render_target.
+    pub render_targets: usize,
+    // Multiplies resolved colours, after fog and before any encoding or clamping, 1.0 by default.
+    // Brightens or darkens the whole frame without touching the lights, float output included.
+    pub exposure: f32,
+    // Treats shaded colours as linear and sRGB encodes them when writing 8 bit output, off by
+    // default for shaders that already output display colours. Float output stays linear.
+    pub output_srgb: bool,
+    // Interpolates varyings and depth across triangles and lines correcting for perspective, on by
+    // default. Turning it off for screen aligned 2D, where w is the same at every vertex, gives
+    // the same result without the per pixel divide, elsewhere textures visibly swim.
+    pub perspective_correct: bool,
+    // Skips triangles covering less than this many square pixels on screen, trading the odd
+    // dropped pixel on distant dense meshes for not setting them up. Applied after clipping.
+    pub min_triangle_area: Option<f32>,
+    // Bounds how many translucent fragments each pixel keeps, None by default for no limit.
+    // Past it fragments hidden behind opaque ones are dropped and the farthest are merged,
+    // exact for TransparencyMode::Sorted unless a later fragment lands behind the merged ones.
+    // Each fragment added past the limit costs a pass over the pixel's fragments.
+    pub max_fragments_per_pixel: Option<usize>,
+    // Parallel drawing and post processing run inside this pool, None uses rayon's global pool
+    pub thread_pool: Option<Arc<ThreadPool>>,
+    // Off by default to keep the atomic counting out of the per fragment path
+    pub collect_stats: bool,
+}
+
+#[derive(Default, Copy, Clone)]
+pub enum TransparencyMode {
+    // Exact back to front compositing, sorting every pixel's transparent fragments
+    #[default]
+    Sorted,
+    // Order independent approximation that skips the sort, cheaper with many overlapping layers
+    WeightedBlended,
+}
+
+#[derive(Default, Copy, Clone)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    // Fills, then draws each visible triangle's edges over it in line_colour. Edges behind other
+    // geometry stay hidden, they're only biased enough to win against their own face.
+    FillAndLine { line_colour: Vector3<f32> },
+}
+
+// In normalized device depth, on top of a pixel's worth of the face's depth slope since
+// a line pixel can sit up to about a pixel from where the face would be sampled
+const LINE_DEPTH_BIAS: f32 = 1e-5;
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        Self {
+            cull_backfaces: false,
+            polygon_mode: PolygonMode::Fill,
+            background: Background::default(),
+            stencil: None,
+            alpha_cutoff: None,
+            transparency: TransparencyMode::Sorted,
+            fog: None,
+            depth_mode: DepthMode::Standard,
+            sort_front_to_back: false,
+            conservative: false,
+            reject_outside_depth_range: true,
+            depth_bias: 0.0,
+            slope_scaled_depth_bias: 0.0,
+            render_targets: 0,
+            exposure: 1.0,
+            output_srgb: false,
+            perspective_correct: true,
+            min_triangle_area: None,
+            max_fragments_per_pixel: None,
+            thread_pool: None,
+            collect_stats: false,
+        }
+    }
+}
+
+struct DrawContext<'a, S: Shader> {
+    storage: &'a Storage,
+    options: &'a RasterOptions,
+    shader: &'a S,
+    fragment_counters: Option<&'a FragmentCounters>,
 }
 
 pub struct Rasterizer {
-    width: usize,
-    height: usize,
     storage: Storage,
     viewport: Matrix4<f32>,
+    viewport_rect: BoundingBox,
     options: RasterOptions,
-    render_buffer: Vec<RenderBufferPixel>
+    framebuffer: Framebuffer,
+    stats: RasterStats,
+    // Fragments passed since begin_occlusion_query, None outside a query
+    occlusion_query: Option<u64>,
 }
 
 impl Rasterizer {
     pub fn new(width: usize, height: usize, options: RasterOptions) -> Self {
         let viewport = Self::build_viewport_matrix((0.0, 0.0), width as f32, height as f32);
-        
-        let mut alpha_buffer = Vec::with_capacity(width * height);
-        for _ in 0..width * height {
-            alpha_buffer.push(RenderBufferPixel::new(options.background_colour));
-        }
+        let framebuffer = Framebuffer::new(width, height, options.depth_mode, options.render_targets);
 
         Self {
-            width,
             storage: Storage::default(),
-            height,
             viewport,
+            viewport_rect: Self::full_frame(width, height),
             options,
-            render_buffer: alpha_buffer,
+            framebuffer,
+            stats: RasterStats::default(),
+            occlusion_query: None,
         }
     }
 
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.viewport = Self::build_viewport_matrix((0.0, 0.0), width as f32, height as f32);
+        self.viewport_rect = Self::full_frame(width, height);
+        self.framebuffer.resize(width, height);
+    }
+
+    // Maps clip space onto the given rectangle, with (x, y) the top left corner in pixels.
+    // Fragments outside the rectangle are skipped, resize resets it to the full frame.
+    pub fn set_viewport(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        self.viewport = Self::build_viewport_matrix((x as f32, y as f32), width as f32, height as f32);
+        self.viewport_rect = BoundingBox::new(Vector2::new(x, y), Vector2::new(x + width, y + height))
+            .intersection(&Self::full_frame(self.framebuffer.width(), self.framebuffer.height()));
+    }
+
+    fn full_frame(width: usize, height: usize) -> BoundingBox {
+        BoundingBox::new(Vector2::new(0, 0), Vector2::new(width, height))
+    }
+
     fn build_viewport_matrix(margin: (f32, f32), width: f32, height: f32) -> Matrix4<f32> {
         Matrix4::new(
             width / 2.0, 0.0,           0.0, margin.0 + width / 2.0,
@@ -53,151 +204,636 @@ impl Rasterizer {
         )
     }
     
-    fn calculate_barycentric_coordinates(
-        vertex_positions: [Vector2<f32>; 3],
-        pixel: Vector2<f32>,
-    ) -> Vector3<f32> {
+    // Edge functions for the edges opposite each vertex, as (a, b, c) with a * x + b * y + c
+    // twice the signed area spanned by the edge and the point. An edge shared with a neighbour
+    // evaluates to exactly the negated value there, so the fill rule can split ties cleanly.
+    fn edge_functions(vertex_positions: [Vector2<f32>; 3]) -> [Vector3<f32>; 3] {
         let [a, b, c] = vertex_positions;
 
-        // Calculate the area of the full triangle using cross product
-        let area = 0.5 * (
-            (b.x - a.x) * (c.y - a.y) -
-                (c.x - a.x) * (b.y - a.y)
-        );
+        [(b, c), (c, a), (a, b)].map(|(start, end)| Vector3::new(
+            start.y - end.y,
+            end.x - start.x,
+            start.x * end.y - end.x * start.y,
+        ))
+    }
 
-        // Calculate barycentric coordinates using areas of sub-triangles
-        let alpha = 0.5 * (
-            (b.x - pixel.x) * (c.y - pixel.y) -
-                (c.x - pixel.x) * (b.y - pixel.y)
-        ) / area;
+    // Top-left fill rule, a pixel centre exactly on an edge shared by two triangles is only
+    // covered by the one the edge is a left edge of, with the interior to its right, or a top
+    // edge of, flat with the interior below. Indexed like the barycentrics, by opposite vertex.
+    fn top_left_edges(edge_functions: &[Vector3<f32>; 3], area_sign: f32) -> [bool; 3] {
+        edge_functions.map(|edge| {
+            // Direction the barycentric grows in, into the triangle, with y down the screen
+            let inward = edge.xy() * area_sign;
+            inward.x > 0.0 || (inward.x == 0.0 && inward.y > 0.0)
+        })
+    }
+
+    fn cull_triangle(vertex_positions: &[Vector4<f32>; 3], options: &RasterOptions) -> bool {
+        options.cull_backfaces && Self::is_backface(vertex_positions)
+    }
 
-        let beta = 0.5 * (
-            (c.x - pixel.x) * (a.y - pixel.y) -
-                (a.x - pixel.x) * (c.y - pixel.y)
-        ) / area;
+    // Stops at the first item whose shader bindings aren't satisfied, earlier items stay drawn
+    pub fn draw_scene(&mut self, scene: &Scene) -> Result<(), BindingError> {
+        for item in scene.draw_order() {
+            self.storage.set_mat4s(vec![scene.view_projection, item.transform]);
+            self.storage.set_texture2d_indices(&item.texture_indices);
 
-        let gamma = 1.0 - alpha - beta;
+            if !Self::is_box_visible(item.geometry.bounding_box(), &(scene.view_projection * item.transform), self.options.depth_mode) {
+                if self.options.collect_stats {
+                    self.stats.meshes_culled += 1;
+                }
+                continue;
+            }
 
-        Vector3::new(alpha, beta, gamma)
+            match item.geometry {
+                Geometry::Mesh(mesh) => self.draw_mesh(mesh, &item.shader)?,
+                Geometry::Indexed(mesh) => self.draw_indexed_mesh(mesh, &item.shader)?,
+                Geometry::Quads(mesh) => self.draw_quad_mesh(mesh, &item.shader)?,
+            }
+        }
+        Ok(())
     }
 
-    fn cull_triangle(vertex_positions: &[Vector4<f32>; 3], options: &RasterOptions) -> bool {
-        Self::triangle_outside_screen(vertex_positions)
-            || (options.cull_backfaces && Self::is_backface(vertex_positions))
+    // A material without a texture leaves the current texture indices bound
+    fn bind_material(&mut self, material: &Material) {
+        self.storage.set_material(*material);
+        if let Some(texture) = material.texture {
+            self.storage.set_texture2d_indices(&[texture]);
+        }
+    }
+
+    // The draw methods check the shader's required bindings first and draw nothing when one is missing
+    pub fn draw_mesh(&mut self, mesh: &Mesh, shader: &impl Shader) -> Result<(), BindingError> {
+        self.draw_mesh_instanced(mesh, &[Matrix4::identity()], shader)
+    }
+
+    // Only mesh.faces[range], the start included and the end excluded, with the mesh's material.
+    // Panics when the range isn't within the faces, as slicing them would.
+    pub fn draw_mesh_range(&mut self, mesh: &Mesh, range: Range<usize>, shader: &impl Shader) -> Result<(), BindingError> {
+        let faces = mesh.faces.get(range.clone())
+            .unwrap_or_else(|| panic!("Face range {range:?} is out of bounds for a mesh with {} faces", mesh.faces.len()));
+
+        self.draw_faces_instanced(faces, &mesh.material, &[Matrix4::identity()], shader)
+    }
+
+    // Skips the mesh when its bounding box, transformed by the same matrix the shader
+    // uses to reach clip space, is entirely outside the view frustum. Returns whether it was drawn.
+    pub fn draw_mesh_culled(&mut self, mesh: &Mesh, model_view_projection: &Matrix4<f32>, shader: &impl Shader) -> Result<bool, BindingError> {
+        if !Self::is_box_visible(mesh.bounding_box(), model_view_projection, self.options.depth_mode) {
+            if self.options.collect_stats {
+                self.stats.meshes_culled += 1;
+            }
+            return Ok(false);
+        }
+
+        self.draw_mesh(mesh, shader)?;
+        Ok(true)
+    }
+
+    // A size.x by size.y quad around centre, in world units, facing the camera and textured by
+    // the texture at texture_index, which is left bound to slot 0. The camera's right and up
+    // axes are read from mat4 0 taken as a perspective view projection, as InstancedShader uses.
+    // It's depth tested like other geometry and blended when the texture is translucent.
+    pub fn draw_billboard(&mut self, centre: Vector3<f32>, size: Vector2<f32>, texture_index: usize) -> Result<(), BindingError> {
+        self.storage.set_texture2d_indices(&[texture_index]);
+        self.storage.validate(&InstancedShader.required_bindings())?;
+
+        // Projection only scales the view's x and y rows, so their directions are the camera's axes
+        let view_projection = self.storage.get_mat4(0);
+        let right = view_projection.fixed_view::<1, 3>(0, 0).transpose().try_normalize(f32::EPSILON).unwrap_or(Vector3::x());
+        let up = view_projection.fixed_view::<1, 3>(1, 0).transpose().try_normalize(f32::EPSILON).unwrap_or(Vector3::y());
+
+        let quad = Mesh::quad_spanning(centre, right * size.x, up * size.y);
+        self.draw_mesh(&quad, &InstancedShader)
     }
 
-    pub fn draw_mesh(&mut self, mesh: &Mesh, shader: &impl Shader) {
-        let faces = mesh.faces.iter().map(|face| {
-            let vertex_outputs = self.run_vertex_shader(&face, shader);
-            let vertex_positions = [
-                vertex_outputs[0].position,
-                vertex_outputs[1].position,
-                vertex_outputs[2].position,
-            ];
+    pub fn is_box_visible((min, max): (Vector3<f32>, Vector3<f32>), model_view_projection: &Matrix4<f32>, depth_mode: DepthMode) -> bool {
+        let corners = (0..8)
+            .map(|corner| {
+                let x = if corner & 1 == 0 { min.x } else { max.x };
+                let y = if corner & 2 == 0 { min.y } else { max.y };
+                let z = if corner & 4 == 0 { min.z } else { max.z };
+                model_view_projection * Vector4::new(x, y, z, 1.0)
+            })
+            .collect::<Vec<_>>();
+
+        !clipper::box_outside(&corners, depth_mode)
+    }
 
-            (vertex_positions, vertex_outputs)
-        }).collect::<Vec<_>>();
+    pub fn draw_mesh_instanced(&mut self, mesh: &Mesh, transforms: &[Matrix4<f32>], shader: &impl Shader) -> Result<(), BindingError> {
+        self.draw_faces_instanced(&mesh.faces, &mesh.material, transforms, shader)
+    }
 
-        let num_threads = rayon::current_num_threads();
+    fn draw_faces_instanced(&mut self, faces: &[Face], material: &Material, transforms: &[Matrix4<f32>], shader: &impl Shader) -> Result<(), BindingError> {
+        self.bind_material(material);
+        self.storage.validate(&shader.required_bindings())?;
 
-        let rows_per_thread = self.height / num_threads;
-        
-        self.render_buffer.par_chunks_mut(self.width * rows_per_thread)
+        let rasterizer = &*self;
+        let vertex_outputs = transforms.iter()
             .enumerate()
-            .for_each(|(row_num, alpha_buffer_row)| {
-                let start = row_num * rows_per_thread;
-                let end = start + rows_per_thread;
-                
-                let bounding_box = BoundingBox::new(Vector2::new(0, start), Vector2::new(self.width - 1, end));
-
-                for (vertex_positions, vertex_outputs) in &faces {
-                    Self::draw_triangle(vertex_positions, &self.options, &self.viewport, bounding_box, self.width, start, alpha_buffer_row, &self.storage, vertex_outputs, shader);
+            .flat_map(|(instance_id, transform)| faces.iter().map(move |face| {
+                face.vertices.map(|vertex| rasterizer.run_vertex_shader(&vertex, instance_id, transform, shader))
+            }))
+            .collect::<Vec<_>>();
+
+        let triangles = vertex_outputs.iter()
+            .map(|outputs| [&outputs[0], &outputs[1], &outputs[2]])
+            .collect::<Vec<_>>();
+
+        self.draw_triangles(&triangles, shader);
+        Ok(())
+    }
+
+    pub fn draw_quad_mesh(&mut self, mesh: &QuadMesh, shader: &impl Shader) -> Result<(), BindingError> {
+        self.bind_material(&mesh.material);
+        self.storage.validate(&shader.required_bindings())?;
+
+        let vertex_outputs = mesh.quads.iter()
+            .map(|quad| quad.vertices.map(|vertex| self.run_vertex_shader(&vertex, 0, &Matrix4::identity(), shader)))
+            .collect::<Vec<_>>();
+
+        let triangles = vertex_outputs.iter()
+            .flat_map(|outputs| Quad::TRIANGLES.map(|indices| indices.map(|index| &outputs[index])))
+            .collect::<Vec<_>>();
+
+        self.draw_triangles(&triangles, shader);
+        Ok(())
+    }
+
+    pub fn draw_indexed_mesh(&mut self, mesh: &IndexedMesh, shader: &impl Shader) -> Result<(), BindingError> {
+        self.bind_material(&mesh.material);
+        self.storage.validate(&shader.required_bindings())?;
+
+        // Vertices are shaded lazily so unreferenced ones are skipped and shared ones run once
+        let mut vertex_outputs: Vec<Option<VertexShaderOutputVariables>> = Vec::with_capacity(mesh.vertices.len());
+        vertex_outputs.resize_with(mesh.vertices.len(), || None);
+
+        for &index in mesh.indices.iter().flatten() {
+            let index = index as usize;
+            vertex_outputs[index].get_or_insert_with(|| self.run_vertex_shader(&mesh.vertices[index], 0, &Matrix4::identity(), shader));
+        }
+
+        let triangles = mesh.indices.iter()
+            .map(|indices| indices.map(|index| vertex_outputs[index as usize].as_ref().unwrap()))
+            .collect::<Vec<_>>();
+
+        self.draw_triangles(&triangles, shader);
+        Ok(())
+    }
+
+    // Each vertex after the second makes a triangle with the two before it. Every other one is
+    // flipped, as (0, 1, 2), (1, 3, 2), (2, 3, 4), so the whole strip keeps the first triangle's
+    // winding and triangle n still starts at vertex n, whose flat varyings it takes.
+    pub fn draw_strip(&mut self, vertices: &[Vertex], shader: &impl Shader) -> Result<(), BindingError> {
+        self.storage.validate(&shader.required_bindings())?;
+
+        let vertex_outputs = self.shade_vertices(vertices, shader);
+        let triangles = (0..vertices.len().saturating_sub(2))
+            .map(|first| if first % 2 == 0 { [first, first + 1, first + 2] } else { [first, first + 2, first + 1] })
+            .map(|indices| indices.map(|index| &vertex_outputs[index]))
+            .collect::<Vec<_>>();
+
+        self.draw_triangles(&triangles, shader);
+        Ok(())
+    }
+
+    // Each vertex after the second makes a triangle with the one before it and the first
+    pub fn draw_fan(&mut self, vertices: &[Vertex], shader: &impl Shader) -> Result<(), BindingError> {
+        self.storage.validate(&shader.required_bindings())?;
+
+        let vertex_outputs = self.shade_vertices(vertices, shader);
+        let triangles = (1..vertices.len().saturating_sub(1))
+            .map(|index| [&vertex_outputs[0], &vertex_outputs[index], &vertex_outputs[index + 1]])
+            .collect::<Vec<_>>();
+
+        self.draw_triangles(&triangles, shader);
+        Ok(())
+    }
+
+    // Draws one triangle from vertex outputs made by hand, skipping the vertex stage. Their
+    // positions are clip space, as a vertex shader would output, and the triangle is clipped,
+    // culled and filled like any other, with the shader's fragment stage reading the varyings.
+    pub fn draw_triangle_raw(&mut self, vertex_outputs: &[VertexShaderOutputVariables; 3], shader: &impl Shader) -> Result<(), BindingError> {
+        self.storage.validate(&shader.required_bindings())?;
+
+        self.draw_triangles(&[vertex_outputs.each_ref()], shader);
+        Ok(())
+    }
+
+    fn shade_vertices(&self, vertices: &[Vertex], shader: &impl Shader) -> Vec<VertexShaderOutputVariables> {
+        vertices.iter()
+            .map(|vertex| self.run_vertex_shader(vertex, 0, &Matrix4::identity(), shader))
+            .collect()
+    }
+
+    fn draw_triangles(&mut self, triangles: &[[&VertexShaderOutputVariables; 3]], shader: &impl Shader) {
+        let clip_results = triangles.iter()
+            .map(|triangle| clipper::clip_triangle(triangle, self.options.depth_mode))
+            .collect::<Vec<_>>();
+
+        let mut clipped_triangles = Vec::with_capacity(triangles.len());
+        for (triangle, clip_result) in triangles.iter().zip(&clip_results) {
+            match clip_result {
+                ClipResult::Inside => clipped_triangles.push(*triangle),
+                ClipResult::Outside => {},
+                ClipResult::Clipped(polygon) => clipped_triangles.extend(clipper::triangulate(polygon)),
+            }
+        }
+
+        let visible_triangles = clipped_triangles.iter()
+            .filter(|vertex_outputs| !Self::cull_triangle(&vertex_outputs.map(|output| output.position), &self.options))
+            .collect::<Vec<_>>();
+
+        let mut screen_triangles = visible_triangles.into_iter()
+            .map(|vertex_outputs| ScreenTriangle::new(*vertex_outputs, &self.viewport))
+            .filter(|triangle| self.options.min_triangle_area.is_none_or(|min_area| triangle.area() >= min_area))
+            .collect::<Vec<_>>();
+
+        if self.options.collect_stats {
+            let clipped = clip_results.iter().filter(|result| matches!(result, ClipResult::Outside)).count();
+            self.stats.triangles_submitted += triangles.len() as u64;
+            self.stats.triangles_clipped += clipped as u64;
+            self.stats.triangles_culled += (clipped_triangles.len() - screen_triangles.len()) as u64;
+        }
+
+        if self.options.sort_front_to_back {
+            let depth_mode = self.options.depth_mode;
+            screen_triangles.sort_by(|a, b| a.nearest_distance(depth_mode).total_cmp(&b.nearest_distance(depth_mode)));
+        }
+
+        let counters = FragmentCounters::default();
+        let context = DrawContext {
+            storage: &self.storage,
+            options: &self.options,
+            shader,
+            fragment_counters: self.counting_fragments().then_some(&counters),
+        };
+        let framebuffer = &mut self.framebuffer;
+        let viewport_rect = self.viewport_rect;
+        let margin = Self::coverage_margin(&self.options);
+        install(self.options.thread_pool.as_deref(), || {
+            // Laid out inside the pool so there's a chunk per thread of the pool in use
+            let layout = ChunkLayout::new(framebuffer.width(), framebuffer.height(), viewport_rect);
+            let bins = layout.bin(&screen_triangles, |triangle| {
+                let (min_y, max_y) = triangle.y_range();
+                (min_y - margin, max_y + margin)
+            });
+
+            layout.for_each_chunk(framebuffer, |chunk| {
+                for triangle in &bins[chunk.index] {
+                    Self::draw_triangle(chunk, &context, triangle);
+                }
+            });
+        });
+        self.add_fragments(&counters);
+
+        if let PolygonMode::FillAndLine { line_colour } = self.options.polygon_mode {
+            let edges = screen_triangles.iter()
+                .flat_map(|triangle| {
+                    let depth_bias = triangle.max_depth_slope() + LINE_DEPTH_BIAS;
+                    [(0, 1), (1, 2), (2, 0)].map(|(start, end)| {
+                        ScreenLine::new([triangle.vertex_outputs[start], triangle.vertex_outputs[end]], &self.viewport)
+                            .with_depth_bias(depth_bias)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            self.draw_screen_lines(&edges, &LineColourShader(line_colour));
+        }
+    }
+
+    pub fn draw_lines(&mut self, mesh: &Mesh, shader: &impl Shader) -> Result<(), BindingError> {
+        self.storage.validate(&shader.required_bindings())?;
+
+        let vertex_outputs = mesh.faces.iter()
+            .flat_map(|face| &face.vertices)
+            .map(|vertex| self.run_vertex_shader(vertex, 0, &Matrix4::identity(), shader))
+            .collect::<Vec<_>>();
+
+        let lines = vertex_outputs.chunks_exact(2)
+            .map(|pair| [&pair[0], &pair[1]])
+            .collect::<Vec<_>>();
+
+        let clip_results = lines.iter()
+            .map(|line| clipper::clip_line(line, self.options.depth_mode))
+            .collect::<Vec<_>>();
+
+        let mut screen_lines = Vec::with_capacity(lines.len());
+        for (line, clip_result) in lines.iter().zip(&clip_results) {
+            match clip_result {
+                ClipResult::Inside => screen_lines.push(ScreenLine::new(*line, &self.viewport)),
+                ClipResult::Outside => {},
+                ClipResult::Clipped(clipped) => screen_lines.push(ScreenLine::new([&clipped[0], &clipped[1]], &self.viewport)),
+            }
+        }
+
+        self.draw_screen_lines(&screen_lines, shader);
+        Ok(())
+    }
+
+    fn draw_screen_lines(&mut self, screen_lines: &[ScreenLine], shader: &impl Shader) {
+        let counters = FragmentCounters::default();
+        let context = DrawContext {
+            storage: &self.storage,
+            options: &self.options,
+            shader,
+            fragment_counters: self.counting_fragments().then_some(&counters),
+        };
+        let framebuffer = &mut self.framebuffer;
+        let viewport_rect = self.viewport_rect;
+        install(self.options.thread_pool.as_deref(), || {
+            let layout = ChunkLayout::new(framebuffer.width(), framebuffer.height(), viewport_rect);
+            let bins = layout.bin(screen_lines, ScreenLine::y_range);
+
+            layout.for_each_chunk(framebuffer, |chunk| {
+                for line in &bins[chunk.index] {
+                    Self::draw_line(chunk, &context, line);
+                }
+            });
+        });
+        self.add_fragments(&counters);
+    }
+
+    fn draw_line(
+        chunk: &mut RasterChunk,
+        context: &DrawContext<impl Shader>,
+        line: &ScreenLine,
+    ) {
+        let [start, end] = line.screen_coords_2d;
+        let [start_w, end_w] = line.screen_coords_pre_perspective.map(|coords| coords.w);
+        let [start_output, end_output] = line.vertex_outputs;
+
+        // The fragment stage interpolates across three vertices, the third is given no weight
+        let vertex_outputs = [start_output, end_output, end_output];
+
+        let min = chunk.bounding_box.min();
+        let max = chunk.bounding_box.max();
+
+        // Only the part rounding to pixels in the chunk is stepped through, a long line crossing
+        // many chunks would otherwise be walked end to end by each of them
+        let clip_min = min.map(|coord| coord as f32 - 0.5);
+        let clip_max = max.map(|coord| coord as f32 - 0.5);
+        let Some((t_start, t_end)) = line.clip_to_rect(clip_min, clip_max) else { return };
+
+        // DDA, one step per pixel along the major axis. The steps are the whole line's so every
+        // chunk lands on the same pixels, clipping only picks which of them to visit.
+        let steps = (end - start).abs().max().ceil().max(1.0) as usize;
+        let first_step = (t_start * steps as f32).floor() as usize;
+        let last_step = ((t_end * steps as f32).ceil() as usize).min(steps);
+        for step in first_step..=last_step {
+            let t = step as f32 / steps as f32;
+            let position = start.lerp(&end, t).map(f32::round);
+            if position.x < min.x as f32 || position.y < min.y as f32 { continue }
+
+            let (x, y) = (position.x as usize, position.y as usize);
+            if x >= max.x || y >= max.y { continue }
+
+            let bary_clip = if context.options.perspective_correct {
+                let bary_over_w = Vector2::new((1.0 - t) / start_w, t / end_w);
+                bary_over_w / (bary_over_w.x + bary_over_w.y)
+            } else {
+                Vector2::new(1.0 - t, t)
+            };
+            let bary_clip = Vector3::new(bary_clip.x, bary_clip.y, 0.0);
+
+            let frag_depth = bary_clip.x * start_output.position.z + bary_clip.y * end_output.position.z;
+            let frag_w = bary_clip.x * start_output.position.w + bary_clip.y * end_output.position.w;
+            let frag_depth = context.options.depth_mode.nearer_by(frag_depth, line.depth_bias * frag_w);
+
+            Self::draw_pixel(chunk.pixel_mut(x, y), context, frag_depth, frag_w, bary_clip, [Vector3::zeros(); 2], &vertex_outputs);
+        }
+    }
+
+    pub fn draw_points(&mut self, mesh: &Mesh, size: f32, shader: &impl Shader) -> Result<(), BindingError> {
+        self.storage.validate(&shader.required_bindings())?;
+
+        let vertex_outputs = mesh.faces.iter()
+            .flat_map(|face| &face.vertices)
+            .map(|vertex| self.run_vertex_shader(vertex, 0, &Matrix4::identity(), shader))
+            .filter(|output| clipper::point_inside(&output.position, self.options.depth_mode))
+            .collect::<Vec<_>>();
+
+        let points = vertex_outputs.iter()
+            .map(|output| {
+                let screen_coords = self.viewport * output.position;
+                (output, screen_coords.xy() / screen_coords.w)
+            })
+            .collect::<Vec<_>>();
+
+        let counters = FragmentCounters::default();
+        let context = DrawContext {
+            storage: &self.storage,
+            options: &self.options,
+            shader,
+            fragment_counters: self.counting_fragments().then_some(&counters),
+        };
+        let framebuffer = &mut self.framebuffer;
+        let viewport_rect = self.viewport_rect;
+        install(self.options.thread_pool.as_deref(), || {
+            let layout = ChunkLayout::new(framebuffer.width(), framebuffer.height(), viewport_rect);
+            let bins = layout.bin(&points, |(_, centre)| (centre.y - size / 2.0, centre.y + size / 2.0));
+
+            layout.for_each_chunk(framebuffer, |chunk| {
+                for (output, centre) in &bins[chunk.index] {
+                    Self::draw_point(chunk, &context, output, *centre, size);
                 }
+            });
         });
+        self.add_fragments(&counters);
+        Ok(())
+    }
+
+    fn draw_point(
+        chunk: &mut RasterChunk,
+        context: &DrawContext<impl Shader>,
+        vertex_output: &VertexShaderOutputVariables,
+        centre: Vector2<f32>,
+        size: f32,
+    ) {
+        // Covers the pixel centres within the half-open square [centre - size/2, centre + size/2)
+        let min = chunk.bounding_box.min();
+        let max = chunk.bounding_box.max();
+        let min_x = ((centre.x - size / 2.0).ceil().max(0.0) as usize).max(min.x);
+        let max_x = ((centre.x + size / 2.0).ceil().max(0.0) as usize).min(max.x);
+        let min_y = ((centre.y - size / 2.0).ceil().max(0.0) as usize).max(min.y);
+        let max_y = ((centre.y + size / 2.0).ceil().max(0.0) as usize).min(max.y);
+
+        let vertex_outputs = [vertex_output; 3];
+        let bary_coords = Vector3::new(1.0, 0.0, 0.0);
+        let frag_depth = vertex_output.position.z;
+        let frag_w = vertex_output.position.w;
+
+        for x in min_x..max_x {
+            for y in min_y..max_y {
+                Self::draw_pixel(chunk.pixel_mut(x, y), context, frag_depth, frag_w, bary_coords, [Vector3::zeros(); 2], &vertex_outputs);
+            }
+        }
     }
     
     fn draw_triangle(
-        vertex_positions: &[Vector4<f32>; 3],
-        options: &RasterOptions,
-        viewport: &Matrix4<f32>,
-        bounding_box: BoundingBox,
-        width: usize,
-        start: usize,
-        alpha_buffer_row: &mut [RenderBufferPixel],
-        storage: &Storage,
-        vertex_outputs: &[VertexShaderOutputVariables; 3],
-        shader: &impl Shader,
+        chunk: &mut RasterChunk,
+        context: &DrawContext<impl Shader>,
+        triangle: &ScreenTriangle,
     ) {
-        if Self::cull_triangle(vertex_positions, options) { return }
+        let vertex_positions = &triangle.vertex_positions();
+        let vertex_outputs = &triangle.vertex_outputs;
+        let screen_coords_pre_perspective = triangle.screen_coords_pre_perspective;
+        let screen_coords_2d = triangle.screen_coords_2d;
 
-        let screen_coords_pre_perspective = [
-            viewport * vertex_positions[0],
-            viewport * vertex_positions[1],
-            viewport * vertex_positions[2],
-        ];
+        let margin = Self::coverage_margin(context.options);
+        let Some(triangle_bounding_box) = BoundingBox::from_triangle(screen_coords_2d, chunk.bounding_box, margin) else { return };
 
-        let screen_coords_2d = [
-            screen_coords_pre_perspective[0].xy() / screen_coords_pre_perspective[0].w,
-            screen_coords_pre_perspective[1].xy() / screen_coords_pre_perspective[1].w,
-            screen_coords_pre_perspective[2].xy() / screen_coords_pre_perspective[2].w,
-        ];
+        // Screen space barycentrics are affine so their per pixel steps are constant over the triangle
+        let vertex_w = Vector3::new(
+            screen_coords_pre_perspective[0].w,
+            screen_coords_pre_perspective[1].w,
+            screen_coords_pre_perspective[2].w,
+        );
+        let inverse_w = vertex_w.map(|w| 1.0 / w);
+        let perspective_correct = context.options.perspective_correct;
 
-        let triangle_bounding_box = BoundingBox::from_triangle(screen_coords_2d, bounding_box);
+        let depth_bias = context.options.depth_bias + context.options.slope_scaled_depth_bias * triangle.max_depth_slope();
 
-        for x in triangle_bounding_box.x_iter() {
-            for y in triangle_bounding_box.y_iter() {
-                let bary_coords = Self::calculate_barycentric_coordinates(screen_coords_2d, Vector2::new(x as f32, y as f32));
-                if (bary_coords.x < 0.0) || (bary_coords.y < 0.0) || (bary_coords.z < 0.0) { continue; }
+        // Everything per pixel is an affine function of x and y set up once here, each edge
+        // function is then two multiplies and adds. They're evaluated directly rather than
+        // accumulated across the row so a shared edge gives both triangles exactly negated values.
+        let edge_functions = Self::edge_functions(screen_coords_2d);
+        let double_area = edge_functions.iter().map(|edge| edge.z).sum::<f32>();
+        let inverse_double_area = 1.0 / double_area;
+        let edge_x = Vector3::new(edge_functions[0].x, edge_functions[1].x, edge_functions[2].x);
+        let edge_y = Vector3::new(edge_functions[0].y, edge_functions[1].y, edge_functions[2].y);
+        let edge_constant = Vector3::new(edge_functions[0].z, edge_functions[1].z, edge_functions[2].z);
+        let bary_steps = [edge_x * inverse_double_area, edge_y * inverse_double_area];
 
-                let bary_clip = Vector3::new(
-                    bary_coords.x / screen_coords_pre_perspective[0].w,
-                    bary_coords.y / screen_coords_pre_perspective[1].w,
-                    bary_coords.z / screen_coords_pre_perspective[2].w,
-                );
-                let bary_clip = bary_clip / (bary_clip.x + bary_clip.y + bary_clip.z);
+        // How far each barycentric can rise within margin pixels of the centre, so testing
+        // against it passes pixels whose square reaches over the edge
+        let coverage_slack = (bary_steps[0].abs() + bary_steps[1].abs()) * margin;
+        let top_left_edges = Self::top_left_edges(&edge_functions, double_area.signum());
 
-                let frag_depth = Self::get_frag_depth(vertex_positions, bary_clip);
+        for y in triangle_bounding_box.y_iter() {
+            let row = edge_y * y as f32 + edge_constant;
+
+            for x in triangle_bounding_box.x_iter() {
+                let bary_coords = (edge_x * x as f32 + row) * inverse_double_area;
+                let covered = bary_coords + coverage_slack;
+                let outside = (0..3).any(|edge| covered[edge] < 0.0 || (covered[edge] == 0.0 && !top_left_edges[edge]));
+                if outside { continue; }
+
+                // Only differs in conservative mode, pulling centres outside the triangle onto it
+                let bary_coords = if bary_coords.min() < 0.0 {
+                    let clamped = bary_coords.map(|coord| coord.max(0.0));
+                    clamped / clamped.sum()
+                } else {
+                    bary_coords
+                };
+
+                let (bary_clip, bary_derivatives, frag_w) = if perspective_correct {
+                    let bary_over_w = bary_coords.component_mul(&inverse_w);
+                    let bary_over_w_sum = bary_over_w.sum();
+                    let bary_clip = bary_over_w / bary_over_w_sum;
+
+                    // Quotient rule on the perspective divide above
+                    let bary_derivatives = bary_steps.map(|step| {
+                        let step_over_w = step.component_mul(&inverse_w);
+                        (step_over_w - bary_clip * step_over_w.sum()) / bary_over_w_sum
+                    });
+
+                    (bary_clip, bary_derivatives, 1.0 / bary_over_w_sum)
+                } else {
+                    (bary_coords, bary_steps, bary_coords.dot(&vertex_w))
+                };
 
-                let index = x + y * width;
-                let alpha_buffer_row_index = index - start * width;
+                let frag_depth = Self::get_frag_depth(vertex_positions, bary_clip);
+                let frag_depth = context.options.depth_mode.nearer_by(frag_depth, depth_bias * frag_w);
 
-                Self::draw_pixel(alpha_buffer_row_index, frag_depth, alpha_buffer_row, storage, bary_clip, vertex_outputs, shader);
+                Self::draw_pixel(chunk.pixel_mut(x, y), context, frag_depth, frag_w, bary_clip, bary_derivatives, vertex_outputs);
             }
         }
     }
     
     fn draw_pixel(
-        alpha_buffer_row_index: usize,
+        target: PixelTarget,
+        context: &DrawContext<impl Shader>,
         frag_depth: f32,
-        alpha_buffer_row: &mut [RenderBufferPixel],
-        storage: &Storage,
+        frag_w: f32,
         bary_clip: Vector3<f32>,
-        vertex_outputs: &[VertexShaderOutputVariables; 3],
-        shader: &impl Shader,
+        bary_derivatives: [Vector3<f32>; 2],
+        vertex_outputs: &[&VertexShaderOutputVariables; 3],
     ) {
-        if frag_depth >= alpha_buffer_row[alpha_buffer_row_index].get_background().depth { return }
+        if context.options.reject_outside_depth_range && !context.options.depth_mode.in_range(frag_depth, frag_w) {
+            return;
+        }
 
-        let Some(colour) = Self::run_fragment_shader(storage, bary_clip, vertex_outputs, shader) else { return };
+        let PixelTarget { pixel, stencil, mut targets } = target;
+        let stencil_options = context.options.stencil.as_ref();
 
-        let alpha = colour.w;
+        if let Some(stencil_options) = stencil_options {
+            if !stencil_options.test(*stencil) {
+                stencil_options.apply(stencil_options.fail_op, stencil);
+                return;
+            }
+        }
 
-        if alpha <= 0.0001 { return }
+        if context.options.depth_mode.is_occluded(frag_depth, pixel.get_background().depth) {
+            if let Some(stencil_options) = stencil_options {
+                stencil_options.apply(stencil_options.depth_fail_op, stencil);
+            }
+            return;
+        }
+
+        let output = Self::run_fragment_shader(context, bary_clip, bary_derivatives, vertex_outputs, frag_depth, pixel.get_background().depth);
+        if let Some(counters) = context.fragment_counters {
+            counters.count_shaded();
+        }
+
+        let (mut colour, extra_targets) = match output {
+            FragmentOutput::Color(colour) => (colour, &[][..]),
+            FragmentOutput::Targets(ref outputs) if !outputs.is_empty() => (outputs[0], &outputs[1..]),
+            _ => {
+                if let Some(counters) = context.fragment_counters {
+                    counters.count_discarded();
+                }
+                return;
+            }
+        };
 
-        alpha_buffer_row[alpha_buffer_row_index].add(Fragment {
+        // Alpha testing discards cutout fragments entirely, survivors are written as opaque
+        if let Some(alpha_cutoff) = context.options.alpha_cutoff {
+            if colour.w < alpha_cutoff {
+                if let Some(counters) = context.fragment_counters {
+                    counters.count_discarded();
+                }
+                return;
+            }
+            colour.w = 1.0;
+        }
+
+        // Updated before the alpha check so fully transparent colours can still write the stencil
+        if let Some(stencil_options) = stencil_options {
+            stencil_options.apply(stencil_options.pass_op, stencil);
+        }
+
+        // Too transparent to show, so it doesn't count as passing an occlusion query
+        if colour.w <= 0.0001 {
+            if let Some(counters) = context.fragment_counters {
+                counters.count_discarded();
+            }
+            return;
+        }
+
+        let opaque = pixel.add(Fragment {
             colour,
             depth: frag_depth,
-        });
+            w: frag_w,
+        }, context.options.depth_mode, context.options.max_fragments_per_pixel);
+        if opaque {
+            targets.write(extra_targets);
+        }
     }
 
-    fn triangle_outside_screen(vertex_positions: &[Vector4<f32>; 3]) -> bool {
-        for vertex in vertex_positions {
-            if (vertex.x < -vertex.w || vertex.x > vertex.w) &&
-                (vertex.y < -vertex.w || vertex.y > vertex.w) &&
-                (vertex.z < -vertex.w || vertex.z > vertex.w) {
-                return true;
-            }
-        }
-        false
+    // Half a pixel in conservative mode so a pixel's whole square is tested rather than its centre
+    fn coverage_margin(options: &RasterOptions) -> f32 {
+        if options.conservative { 0.5 } else { 0.0 }
     }
 
     fn is_backface(vertex_positions: &[Vector4<f32>; 3]) -> bool {
@@ -223,48 +859,126 @@ impl Rasterizer {
         ))
     }
     
-    pub fn render_to_buffer(&mut self, buffer: &mut [u32]) {
-        if buffer.len() != self.width * self.height {
-            panic!("Buffer length does not match image size");
-        }
-        
-        for index in 0..self.width * self.height {
-            let colour = self.render_buffer[index].resolve(self.options.background_colour);
-            
-            buffer[index] = Self::convert_colour_to_u32(colour);
-        }
+    pub fn clear(&mut self) {
+        self.framebuffer.clear(self.options.depth_mode, self.options.render_targets);
+        self.stats = RasterStats::default();
     }
 
-    fn convert_colour_to_u32(colour: Vector3<f32>) -> u32 {
-        let r = (colour.x * 255.0) as u8 as u32;
-        let g = (colour.y * 255.0) as u8 as u32;
-        let b = (colour.z * 255.0) as u8 as u32;
-        (r << 16) | (g << 8) | b
+    pub fn stats(&self) -> RasterStats {
+        self.stats
     }
 
-    fn run_vertex_shader(&self, face: &Face, shader: &impl Shader) -> Box<[VertexShaderOutputVariables; 3]> {
-        let mut vertex_outputs = Vec::with_capacity(3);
-        for vertex in &face.vertices {
-            let input_vars = VertexShaderInputVariables {
-                position: vertex.position,
-                texture_coords: vertex.texture_coords,
-                normal: vertex.normals,
-                storage: &self.storage,
-            };
-            let output_vars = shader.vertex(input_vars);
-            vertex_outputs.push(output_vars);
+    // Counts the fragments drawn from here until end_occlusion_query that pass the stencil and
+    // depth tests and aren't discarded, e.g. to skip expensive shading on occluded meshes.
+    // Fragments are only counted while a query or collect_stats is active.
+    pub fn begin_occlusion_query(&mut self) {
+        self.occlusion_query = Some(0);
+    }
+
+    // Zero when no query was begun
+    pub fn end_occlusion_query(&mut self) -> u64 {
+        self.occlusion_query.take().unwrap_or(0)
+    }
+
+    fn counting_fragments(&self) -> bool {
+        self.options.collect_stats || self.occlusion_query.is_some()
+    }
+
+    fn add_fragments(&mut self, counters: &FragmentCounters) {
+        if self.options.collect_stats {
+            self.stats.add_fragments(counters);
         }
-        match vertex_outputs.try_into().map(Box::new) {
-            Ok(value) => value,
-            Err(_) => {
-                panic!("Vertex shader output array too large");
-            }
+        if let Some(passed) = &mut self.occlusion_query {
+            *passed += counters.passed();
         }
     }
 
-    fn run_fragment_shader(storage: &Storage, bary_coords: Vector3<f32>, vertex_outputs: &[VertexShaderOutputVariables; 3], shader: &impl Shader) -> Option<Vector4<f32>> {
-        let input_vars = FragmentShaderInputVariables::new(vertex_outputs, bary_coords, storage);
-        shader.fragment(input_vars)
+    pub fn render_to_buffer(&self, buffer: &mut [u32]) {
+        self.framebuffer.resolve_into(buffer, self.options.transparency, self.options.fog.as_ref(), self.options.exposure, self.options.output_srgb, self.background_at());
+    }
+
+    // The resolved colours as floats, skipping the 8 bit quantize of render_to_buffer
+    pub fn render_linear(&self, buffer: &mut [Vector3<f32>]) {
+        self.framebuffer.resolve_linear_into(buffer, self.options.transparency, self.options.fog.as_ref(), self.options.exposure, self.background_at());
+    }
+
+    // The resolved colour at a pixel, None outside the framebuffer. Colours written by opaque
+    // fragments come back unchanged, so an id packed as r = id >> 16, g = (id >> 8) & 0xff and
+    // b = id & 0xff, each divided by 255, is recovered by rounding each channel times 255
+    // rather than truncating. Blending, fog and post processing break this exactness.
+    pub fn pixel_at(&self, x: usize, y: usize) -> Option<Vector3<f32>> {
+        self.framebuffer.pixel_at(x, y, self.options.transparency, self.options.fog.as_ref(), self.options.exposure, self.background_at())
+    }
+
+    fn background_at(&self) -> impl Fn(usize, usize) -> Vector3<f32> + '_ {
+        let (width, height) = (self.framebuffer.width(), self.framebuffer.height());
+        let inverse_view_projection = match self.options.background {
+            Background::Skybox(_) => self.storage.try_get_mat4(0).and_then(|matrix| matrix.try_inverse()).unwrap_or_else(Matrix4::identity),
+            _ => Matrix4::identity(),
+        };
+
+        move |x, y| self.options.background.colour_at(x, y, width, height, &inverse_view_projection)
+    }
+
+    pub fn depth_image(&self) -> Vec<u8> {
+        self.framebuffer.depth_image()
+    }
+
+    // Extra target index, counting from the first output after the colour. Holds what the
+    // nearest opaque fragment wrote, zeros where none did. To sample it in a later pass build
+    // a texture with Texture2D::from_rgba_f32, packing values into 0.0 to 1.0 first.
+    pub fn render_target(&self, index: usize) -> &[Vector4<f32>] {
+        self.try_render_target(index)
+            .unwrap_or_else(|| panic!("Render target {index} is out of range, only {} render targets are set", self.framebuffer.target_count()))
+    }
+
+    pub fn try_render_target(&self, index: usize) -> Option<&[Vector4<f32>]> {
+        self.framebuffer.target(index)
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    fn run_vertex_shader(&self, vertex: &Vertex, instance_id: usize, instance_transform: &Matrix4<f32>, shader: &impl Shader) -> VertexShaderOutputVariables {
+        let input_vars = VertexShaderInputVariables {
+            position: vertex.normalized_position(),
+            texture_coords: vertex.texture_coords,
+            normal: vertex.normals,
+            tangent: vertex.tangent,
+            bone_indices: vertex.bone_indices,
+            bone_weights: vertex.bone_weights,
+            instance_id,
+            instance_transform: *instance_transform,
+            storage: &self.storage,
+        };
+        shader.vertex(input_vars)
+    }
+
+    fn run_fragment_shader(
+        context: &DrawContext<impl Shader>,
+        bary_coords: Vector3<f32>,
+        bary_derivatives: [Vector3<f32>; 2],
+        vertex_outputs: &[&VertexShaderOutputVariables; 3],
+        frag_depth: f32,
+        scene_depth: f32,
+    ) -> FragmentOutput {
+        let input_vars = FragmentShaderInputVariables::new(vertex_outputs, bary_coords, context.storage)
+            .with_derivatives(bary_derivatives)
+            .with_depths(frag_depth, scene_depth, context.options.depth_mode);
+        context.shader.fragment(input_vars)
+    }
+
+    pub(crate) fn thread_pool(&self) -> Option<Arc<ThreadPool>> {
+        self.options.thread_pool.clone()
+    }
+
+    pub fn options_mut(&mut self) -> &mut RasterOptions {
+        &mut self.options
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
     }
 
     pub fn storage_mut(&mut self) -> &mut Storage {
@@ -272,4 +986,523 @@ impl Rasterizer {
     }
 }
 
+// Shades the edges drawn by PolygonMode::FillAndLine, which already have their vertex outputs
+struct LineColourShader(Vector3<f32>);
+
+impl Shader for LineColourShader {
+    fn vertex(&self, _input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+        unreachable!("Edges are drawn from the filled triangles' vertex outputs")
+    }
+
+    fn fragment(&self, _input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        FragmentOutput::Color(self.0.push(1.0))
+    }
+}
+
+// Runs op inside the pool when there is one, rayon's parallel iterators then use its threads
+pub(crate) fn install<R: Send>(thread_pool: Option<&ThreadPool>, op: impl FnOnce() -> R + Send) -> R {
+    match thread_pool {
+        Some(thread_pool) => thread_pool.install(op),
+        None => op(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use nalgebra::{Matrix4, Point3, Vector2, Vector3, Vector4};
+    use crate::camera::PerspectiveCamera;
+    use crate::mesh::{Face, Mesh, Quad, QuadMesh, Vertex};
+    use crate::renderer::rasterizer::depth::DepthMode;
+    use crate::renderer::rasterizer::{PolygonMode, RasterOptions, Rasterizer};
+    use crate::renderer::rasterizer::test_support::{drawn_pixels, full_screen_triangle, triangle, SolidShader};
+    use crate::renderer::rasterizer::texture2d::Texture2D;
+    use crate::renderer::scene::{DrawItem, Scene};
+    use crate::shader::{BasicShader, FragmentOutput, FragmentShaderInputVariables, Shader, Varying, VertexShaderInputVariables, VertexShaderOutputVariables};
+
+    const UV: Varying<Vector2<f32>> = Varying::new(0);
+
+    // Writes the interpolated uv into red and green
+    struct UvShader;
+
+    impl Shader for UvShader {
+        fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+            VertexShaderOutputVariables::new(input_vars.storage.get_mat4(0) * input_vars.position)
+                .with(UV, input_vars.texture_coords.xy())
+        }
+
+        fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+            let uv = input_vars.get(UV).unwrap();
+            FragmentOutput::Color(Vector4::new(uv.x, uv.y, 0.0, 1.0))
+        }
+    }
+
+    // A floor at y = -1 from z = -2 to -6, u running along x and v away from the camera
+    fn floor_quad() -> Mesh {
+        let vertex = |x: f32, z: f32| Vertex::from_pos_tex(Vector4::new(x, -1.0, z, 1.0), Vector3::new((x + 1.0) / 2.0, (z + 2.0) / -4.0, 0.0));
+        let [near_left, near_right, far_right, far_left] = [vertex(-1.0, -2.0), vertex(1.0, -2.0), vertex(1.0, -6.0), vertex(-1.0, -6.0)];
+        Mesh::new(None, vec![Face::new([near_left, near_right, far_right]), Face::new([near_left, far_right, far_left])])
+    }
+
+    #[test]
+    fn perspective_correct_uv_matches_the_floor_under_the_pixel() {
+        let size = 60;
+        let fovy = std::f32::consts::FRAC_PI_2;
+        let mut rasterizer = Rasterizer::new(size, size, RasterOptions::default());
+        rasterizer.storage_mut().set_mat4s(vec![PerspectiveCamera::perspective_projection(fovy, 1.0, 0.1, 100.0)]);
+        rasterizer.draw_mesh(&floor_quad(), &UvShader).unwrap();
+
+        // Halfway down the screen between the near and far edges, where affine interpolation gives v = 0.5
+        let (x, y) = (size / 2, size * 2 / 3);
+        let ndc = Vector2::new(2.0 * x as f32 / size as f32 - 1.0, 1.0 - 2.0 * y as f32 / size as f32);
+
+        // Where the view ray through the pixel centre meets the floor
+        let tan = (fovy / 2.0).tan();
+        let direction = Vector3::new(ndc.x * tan, ndc.y * tan, -1.0);
+        let hit = direction * (-1.0 / direction.y);
+        let expected = Vector2::new((hit.x + 1.0) / 2.0, (hit.z + 2.0) / -4.0);
+
+        let colour = rasterizer.pixel_at(x, y).unwrap();
+        assert!((colour.xy() - expected).norm() < 1e-3, "uv {:?}, expected {expected:?}", colour.xy());
+        assert!((colour.y - 0.5).abs() > 0.1);
+    }
+
+    fn stats_rasterizer(width: usize, height: usize) -> Rasterizer {
+        Rasterizer::new(width, height, RasterOptions { collect_stats: true, ..Default::default() })
+    }
+
+    fn projection() -> Matrix4<f32> {
+        PerspectiveCamera::perspective_projection(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0)
+    }
+
+    #[test]
+    fn reversed_depth_keeps_the_nearer_triangle_in_either_order() {
+        let projection = PerspectiveCamera::reversed_z_projection(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        // Covering the whole view at view depth z
+        let covering = |z: f32| triangle([Vector3::new(-1.0, -1.0, -1.0), Vector3::new(3.0, -1.0, -1.0), Vector3::new(-1.0, 3.0, -1.0)].map(|corner| corner * -z));
+        let (near, far) = (covering(-2.0), covering(-4.0));
+        let (red, blue) = (SolidShader::red(), SolidShader(Vector4::new(0.0, 0.0, 1.0, 1.0)));
+
+        for near_first in [true, false] {
+            let mut rasterizer = Rasterizer::new(8, 8, RasterOptions { depth_mode: DepthMode::Reversed, ..Default::default() });
+            rasterizer.storage_mut().set_mat4(0, projection);
+            if near_first {
+                rasterizer.draw_mesh(&near, &red).unwrap();
+                rasterizer.draw_mesh(&far, &blue).unwrap();
+            } else {
+                rasterizer.draw_mesh(&far, &blue).unwrap();
+                rasterizer.draw_mesh(&near, &red).unwrap();
+            }
+
+            for (x, y) in (0..8).flat_map(|y| (0..8).map(move |x| (x, y))) {
+                assert_eq!(rasterizer.pixel_at(x, y), Some(Vector3::x()), "({x}, {y}) with the near triangle drawn first: {near_first}");
+            }
+        }
+    }
+
+    #[test]
+    fn mesh_behind_the_camera_is_culled() {
+        let mut rasterizer = stats_rasterizer(16, 16);
+        rasterizer.storage_mut().set_mat4s(vec![projection()]);
+        let behind = triangle([Vector3::new(-1.0, -1.0, 2.0), Vector3::new(1.0, -1.0, 2.0), Vector3::new(0.0, 1.0, 3.0)]);
+
+        let drawn = rasterizer.draw_mesh_culled(&behind, &projection(), &SolidShader::red()).unwrap();
+
+        assert!(!drawn);
+        assert_eq!(rasterizer.stats().meshes_culled, 1);
+        assert_eq!(rasterizer.stats().fragments_shaded, 0);
+        assert!(!drawn_pixels(&rasterizer).contains(&true));
+    }
+
+    #[test]
+    fn scene_culls_every_geometry_kind() {
+        let mut rasterizer = stats_rasterizer(16, 16);
+        let behind = triangle([Vector3::new(-1.0, -1.0, 2.0), Vector3::new(1.0, -1.0, 2.0), Vector3::new(0.0, 1.0, 3.0)]);
+        let indexed = behind.to_indexed();
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| Vertex::from_pos(Vector4::new(x, y, 2.0, 1.0)));
+        let quads = QuadMesh::new(None, vec![Quad::new(corners)]);
+        let shader = SolidShader::red();
+
+        let mut scene = Scene::new(projection());
+        scene.add(DrawItem::new(&behind, Matrix4::identity(), Vec::new(), &shader));
+        scene.add(DrawItem::new(&indexed, Matrix4::identity(), Vec::new(), &shader));
+        scene.add(DrawItem::new(&quads, Matrix4::identity(), Vec::new(), &shader));
+        rasterizer.draw_scene(&scene).unwrap();
+
+        assert_eq!(rasterizer.stats().meshes_culled, 3);
+        assert_eq!(rasterizer.stats().triangles_submitted, 0);
+    }
+
+    #[test]
+    fn fully_transparent_fragments_fail_occlusion_queries() {
+        let mut rasterizer = Rasterizer::new(16, 16, RasterOptions::default());
+        let full_screen = full_screen_triangle(0.0);
+
+        rasterizer.begin_occlusion_query();
+        rasterizer.draw_mesh(&full_screen, &SolidShader(Vector4::new(1.0, 0.0, 0.0, 0.0))).unwrap();
+        assert_eq!(rasterizer.end_occlusion_query(), 0);
+
+        rasterizer.begin_occlusion_query();
+        rasterizer.draw_mesh(&full_screen, &SolidShader(Vector4::new(1.0, 0.0, 0.0, 0.5))).unwrap();
+        assert_eq!(rasterizer.end_occlusion_query(), 16 * 16);
+    }
+
+    // Sixteen full screen layers submitted far to near, the worst case for overdraw
+    fn layers_far_to_near() -> Mesh {
+        let layers = (0..16)
+            .map(|layer| 0.9 - layer as f32 * 0.1)
+            .map(|z| Mesh::quad_spanning(Vector3::new(0.0, 0.0, z), Vector3::x() * 2.0, Vector3::y() * 2.0))
+            .collect::<Vec<_>>();
+        Mesh::concat(&layers)
+    }
+
+    #[test]
+    fn front_to_back_sorting_shades_only_the_nearest_layer() {
+        let shaded = |sort_front_to_back: bool| {
+            let options = RasterOptions { sort_front_to_back, collect_stats: true, ..Default::default() };
+            let mut rasterizer = Rasterizer::new(32, 32, options);
+            rasterizer.draw_mesh(&layers_far_to_near(), &SolidShader::red()).unwrap();
+            rasterizer.stats().fragments_shaded
+        };
+
+        let (unsorted, sorted) = (shaded(false), shaded(true));
+        assert_eq!(unsorted, 16 * 32 * 32);
+        assert_eq!(sorted, 32 * 32);
+    }
+
+    // Counts its vertex shader runs
+    #[derive(Default)]
+    struct CountingShader(AtomicUsize);
+
+    impl Shader for CountingShader {
+        fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            VertexShaderOutputVariables::new(input_vars.position)
+        }
+
+        fn fragment(&self, _input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+            FragmentOutput::Color(Vector4::repeat(1.0))
+        }
+    }
+
+    #[test]
+    fn indexed_meshes_shade_each_shared_vertex_once() {
+        let mesh = Mesh::uv_sphere(16, 8);
+        let indexed = mesh.to_indexed();
+        let mut rasterizer = Rasterizer::new(32, 32, RasterOptions::default());
+
+        let shader = CountingShader::default();
+        rasterizer.draw_mesh(&mesh, &shader).unwrap();
+        let unindexed_runs = shader.0.into_inner();
 
+        let shader = CountingShader::default();
+        rasterizer.draw_indexed_mesh(&indexed, &shader).unwrap();
+        let indexed_runs = shader.0.into_inner();
+
+        assert_eq!(unindexed_runs, mesh.faces.len() * 3);
+        assert_eq!(indexed_runs, indexed.vertices.len());
+        assert!(indexed_runs * 4 < unindexed_runs, "{indexed_runs} indexed runs against {unindexed_runs}");
+    }
+
+    #[test]
+    fn alpha_cutoff_cuts_out_a_checkerboard() {
+        // 2x2 white texels alternating between mostly opaque and mostly transparent
+        let (kept, cut) = ([255, 255, 255, 200], [255, 255, 255, 50]);
+        let checkerboard = Texture2D::from_rgba(2, 2, [kept, cut, cut, kept].concat()).unwrap();
+
+        // Odd so no pixel centre lands on a texel boundary
+        let mut rasterizer = Rasterizer::new(9, 9, RasterOptions { alpha_cutoff: Some(0.5), ..Default::default() });
+        rasterizer.storage_mut().set_mat4s(vec![Matrix4::identity(), Matrix4::identity()]);
+        rasterizer.storage_mut().set_texture2ds(vec![checkerboard]);
+        rasterizer.storage_mut().set_texture2d_indices(&[0]);
+        rasterizer.draw_mesh(&Mesh::quad_spanning(Vector3::zeros(), Vector3::x() * 2.0, Vector3::y() * 2.0), &BasicShader).unwrap();
+
+        // Behind the checkerboard, only showing through the cut out texels
+        let blue = SolidShader(Vector4::new(0.0, 0.0, 1.0, 1.0));
+        rasterizer.draw_mesh(&full_screen_triangle(0.5), &blue).unwrap();
+
+        let (white, blue) = (Vector3::repeat(1.0), Vector3::z());
+        let quadrant = |x: usize, y: usize| rasterizer.pixel_at(x, y).unwrap();
+        let top_left = quadrant(0, 0);
+        assert!(top_left == white || top_left == blue, "{top_left:?}");
+        let other = if top_left == white { blue } else { white };
+
+        for y in 0..9 {
+            for x in 0..9 {
+                let expected = if (x < 5) == (y < 5) { top_left } else { other };
+                assert_eq!(rasterizer.pixel_at(x, y).unwrap(), expected, "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn billboards_stay_screen_aligned_as_the_camera_turns() {
+        let (width, height) = (64, 64);
+        let camera = PerspectiveCamera::new(Point3::new(1.0, 2.0, 3.0), Vector3::new(0.3, 0.7, 0.5), std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let forward = camera.view.try_inverse().unwrap().transform_vector(&-Vector3::z());
+
+        let mut rasterizer = Rasterizer::new(width, height, RasterOptions::default());
+        rasterizer.storage_mut().set_mat4s(vec![camera.view_projection]);
+        rasterizer.storage_mut().set_texture2ds(vec![Texture2D::from_rgba(1, 1, vec![255; 4]).unwrap()]);
+        // Spanning just under half the width and a quarter of the height, edges clear of pixel centres
+        rasterizer.draw_billboard(camera.position.coords + forward * 4.0, Vector2::new(3.9, 1.9), 0).unwrap();
+
+        // Rolled or turned off the screen's axes it wouldn't fill the rectangle around it
+        let drawn = drawn_pixels(&rasterizer);
+        let drawn_at = |x: usize, y: usize| drawn[y * width + x];
+        let columns = (0..width).filter(|&x| (0..height).any(|y| drawn_at(x, y))).collect::<Vec<_>>();
+        let rows = (0..height).filter(|&y| (0..width).any(|x| drawn_at(x, y))).collect::<Vec<_>>();
+
+        assert_eq!((columns.len(), rows.len()), (31, 15));
+        for &y in &rows {
+            for &x in &columns {
+                assert!(drawn_at(x, y), "({x}, {y}) is inside the billboard's rectangle but wasn't drawn");
+            }
+        }
+    }
+
+    #[test]
+    fn conservative_rasterization_shades_sub_pixel_triangles() {
+        // In pixel coordinates, inside the square between the centres at (2, 2) and (3, 3)
+        let ndc = |x: f32, y: f32| Vector3::new(2.0 * x / 8.0 - 1.0, 1.0 - 2.0 * y / 8.0, 0.0);
+        let sliver = triangle([ndc(2.3, 2.6), ndc(2.7, 2.6), ndc(2.5, 2.3)]);
+
+        let drawn_with = |conservative: bool| {
+            let mut rasterizer = Rasterizer::new(8, 8, RasterOptions { conservative, ..Default::default() });
+            rasterizer.draw_mesh(&sliver, &SolidShader::red()).unwrap();
+            drawn_pixels(&rasterizer).into_iter().filter(|&drawn| drawn).count()
+        };
+
+        assert_eq!(drawn_with(false), 0);
+        assert!(drawn_with(true) > 0);
+    }
+
+    #[test]
+    fn triangles_under_the_minimum_area_are_culled() {
+        let ndc = |x: f32, y: f32| Vector3::new(2.0 * x / 16.0 - 1.0, 1.0 - 2.0 * y / 16.0, 0.0);
+        // Two square pixels covering the centre at (2, 2), and fifty covering the one at (10, 10)
+        let mut triangles = triangle([ndc(1.5, 1.5), ndc(1.5, 3.5), ndc(3.5, 1.5)]);
+        triangles.merge(&triangle([ndc(6.0, 6.0), ndc(6.0, 16.0), ndc(16.0, 6.0)]));
+
+        let drawn_with = |min_triangle_area: Option<f32>| {
+            let mut rasterizer = Rasterizer::new(16, 16, RasterOptions { min_triangle_area, collect_stats: true, ..Default::default() });
+            rasterizer.draw_mesh(&triangles, &SolidShader::red()).unwrap();
+            let drawn = drawn_pixels(&rasterizer);
+            (drawn[2 * 16 + 2], drawn[10 * 16 + 10], rasterizer.stats().triangles_culled)
+        };
+
+        assert_eq!(drawn_with(None), (true, true, 0));
+        assert_eq!(drawn_with(Some(4.0)), (false, true, 1));
+    }
+
+    #[test]
+    fn fill_and_line_draws_the_interior_and_the_edges() {
+        let ndc = |x: f32, y: f32| Vector3::new(2.0 * x / 32.0 - 1.0, 1.0 - 2.0 * y / 32.0, 0.0);
+        let line_colour = Vector3::new(0.0, 1.0, 0.0);
+        let options = RasterOptions { polygon_mode: PolygonMode::FillAndLine { line_colour }, ..Default::default() };
+
+        let mut rasterizer = Rasterizer::new(32, 32, options);
+        rasterizer.draw_mesh(&triangle([ndc(2.0, 2.0), ndc(2.0, 28.0), ndc(28.0, 2.0)]), &SolidShader::red()).unwrap();
+
+        assert_eq!(rasterizer.pixel_at(8, 8), Some(Vector3::x()));
+        for (x, y) in [(12, 2), (2, 12), (15, 15)] {
+            assert_eq!(rasterizer.pixel_at(x, y), Some(line_colour), "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn perspective_correction_changes_nothing_at_constant_w() {
+        // Tilted in depth, but drawn with an identity transform so every vertex has a w of 1
+        let quad = Mesh::quad_spanning(Vector3::zeros(), Vector3::new(1.5, 0.0, 0.5), Vector3::new(0.0, 1.5, -0.25));
+
+        let render = |perspective_correct: bool| {
+            let mut rasterizer = Rasterizer::new(32, 32, RasterOptions { perspective_correct, ..Default::default() });
+            rasterizer.storage_mut().set_mat4(0, Matrix4::identity());
+            rasterizer.draw_mesh(&quad, &UvShader).unwrap();
+
+            let mut buffer = vec![Vector3::zeros(); 32 * 32];
+            rasterizer.render_linear(&mut buffer);
+            buffer
+        };
+
+        // Equal up to the rounding of dividing by w and multiplying back
+        let (corrected, uncorrected) = (render(true), render(false));
+        assert!(corrected.iter().any(|pixel| *pixel != Vector3::zeros()));
+        for (index, (corrected, uncorrected)) in corrected.iter().zip(&uncorrected).enumerate() {
+            assert!((corrected - uncorrected).norm() < 1e-5, "{corrected:?} against {uncorrected:?} at pixel {index}");
+        }
+    }
+
+    const FLAT_COLOUR: Varying<Vector3<f32>> = Varying::flat(0);
+
+    // Fills with each vertex's normal as a flat colour
+    struct FlatColourShader;
+
+    impl Shader for FlatColourShader {
+        fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+            VertexShaderOutputVariables::new(input_vars.position)
+                .with(FLAT_COLOUR, input_vars.normal)
+        }
+
+        fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+            FragmentOutput::Color(input_vars.get(FLAT_COLOUR).unwrap().push(1.0))
+        }
+    }
+
+    fn coloured_vertex(x: f32, y: f32, colour: Vector3<f32>) -> Vertex {
+        Vertex::from_pos_normal(Vector4::new(x, y, 0.0, 1.0), colour)
+    }
+
+    #[test]
+    fn flat_varyings_are_not_blended() {
+        let face = Face::new([
+            coloured_vertex(-1.0, -1.0, Vector3::x()),
+            coloured_vertex(3.0, -1.0, Vector3::y()),
+            coloured_vertex(-1.0, 3.0, Vector3::z()),
+        ]);
+        let mut rasterizer = Rasterizer::new(8, 8, RasterOptions::default());
+        rasterizer.draw_mesh(&Mesh::new(None, vec![face]), &FlatColourShader).unwrap();
+
+        for (x, y) in (0..8).flat_map(|y| (0..8).map(move |x| (x, y))) {
+            assert_eq!(rasterizer.pixel_at(x, y), Some(Vector3::x()), "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn strip_triangles_take_flat_varyings_from_their_first_vertex() {
+        // Two triangles splitting the screen along the diagonal from bottom left to top right
+        let strip = [
+            coloured_vertex(-1.0, 1.0, Vector3::x()),
+            coloured_vertex(-1.0, -1.0, Vector3::y()),
+            coloured_vertex(1.0, 1.0, Vector3::z()),
+            coloured_vertex(1.0, -1.0, Vector3::repeat(1.0)),
+        ];
+        let mut rasterizer = Rasterizer::new(8, 8, RasterOptions { cull_backfaces: true, ..Default::default() });
+        rasterizer.draw_strip(&strip, &FlatColourShader).unwrap();
+
+        assert_eq!(rasterizer.pixel_at(1, 1), Some(Vector3::x()));
+        assert_eq!(rasterizer.pixel_at(6, 6), Some(Vector3::y()));
+    }
+
+    // Drawn with backface culling on, so a triangle whose winding flips drops out
+    fn drawn_culling_backfaces(draw: impl FnOnce(&mut Rasterizer)) -> Vec<bool> {
+        let mut rasterizer = Rasterizer::new(16, 16, RasterOptions { cull_backfaces: true, ..Default::default() });
+        draw(&mut rasterizer);
+        drawn_pixels(&rasterizer)
+    }
+
+    fn clip_vertex(x: f32, y: f32) -> Vertex {
+        Vertex::from_pos(Vector4::new(x, y, 0.0, 1.0))
+    }
+
+    #[test]
+    fn strips_draw_the_same_triangles_as_a_mesh() {
+        // Zigzagging down and up across the left three quarters of the screen
+        let [a, b, c, d, e] = [(-1.0, 1.0), (-1.0, -1.0), (-0.25, 1.0), (-0.25, -1.0), (0.5, 1.0)].map(|(x, y)| clip_vertex(x, y));
+        let faces = vec![Face::new([a, b, c]), Face::new([c, b, d]), Face::new([c, d, e])];
+
+        let strip = drawn_culling_backfaces(|rasterizer| rasterizer.draw_strip(&[a, b, c, d, e], &SolidShader::red()).unwrap());
+        let mesh = drawn_culling_backfaces(|rasterizer| rasterizer.draw_mesh(&Mesh::new(None, faces), &SolidShader::red()).unwrap());
+
+        assert!(mesh[12 * 16 + 5], "The middle triangle wasn't drawn through the mesh");
+        assert_eq!(strip, mesh);
+    }
+
+    #[test]
+    fn fans_draw_the_same_triangles_as_a_mesh() {
+        // Around the bottom left corner, over the whole screen
+        let [a, b, c, d, e, f] = [(-1.0, -1.0), (1.0, -1.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (-1.0, 1.0)].map(|(x, y)| clip_vertex(x, y));
+        let faces = vec![Face::new([a, b, c]), Face::new([a, c, d]), Face::new([a, d, e]), Face::new([a, e, f])];
+
+        let fan = drawn_culling_backfaces(|rasterizer| rasterizer.draw_fan(&[a, b, c, d, e, f], &SolidShader::red()).unwrap());
+        let mesh = drawn_culling_backfaces(|rasterizer| rasterizer.draw_mesh(&Mesh::new(None, faces), &SolidShader::red()).unwrap());
+
+        assert!(mesh.iter().all(|&drawn| drawn));
+        assert_eq!(fan, mesh);
+    }
+
+    #[test]
+    fn mesh_range_draws_only_its_faces() {
+        // Bottom right then top left halves of the screen
+        let quad = Mesh::quad_spanning(Vector3::zeros(), Vector3::x() * 2.0, Vector3::y() * 2.0);
+        let mut rasterizer = stats_rasterizer(8, 8);
+        rasterizer.draw_mesh_range(&quad, 1..2, &SolidShader::red()).unwrap();
+
+        let drawn = drawn_pixels(&rasterizer);
+        assert!(drawn[8 + 1]);
+        assert!(!drawn[6 * 8 + 6]);
+        assert_eq!(rasterizer.stats().triangles_submitted, 1);
+    }
+
+    #[test]
+    fn triangles_sharing_an_edge_shade_each_pixel_once() {
+        // Halves of the screen split by the diagonal from bottom left to top right, which passes
+        // through the pixel centres (1, 7) to (7, 1)
+        let halves = [
+            triangle([Vector3::new(-1.0, -1.0, 0.0), Vector3::new(1.0, 1.0, 0.0), Vector3::new(-1.0, 1.0, 0.0)]),
+            triangle([Vector3::new(-1.0, -1.0, 0.0), Vector3::new(1.0, -1.0, 0.0), Vector3::new(1.0, 1.0, 0.0)]),
+        ];
+
+        let drawn = halves.map(|half| {
+            let mut rasterizer = stats_rasterizer(8, 8);
+            rasterizer.draw_mesh(&half, &SolidShader::red()).unwrap();
+            let drawn = drawn_pixels(&rasterizer);
+            assert_eq!(rasterizer.stats().fragments_shaded, drawn.iter().filter(|&&drawn| drawn).count() as u64);
+            drawn
+        });
+
+        for (index, (first, second)) in drawn[0].iter().zip(&drawn[1]).enumerate() {
+            assert!(first ^ second, "Pixel ({}, {}) is shaded by both halves or neither", index % 8, index / 8);
+        }
+    }
+
+    #[test]
+    fn biased_triangles_win_against_coplanar_ones_in_either_order() {
+        // Tilted so the slope scaled bias has a slope to scale
+        let surface = triangle([Vector3::new(-1.0, -1.0, -0.5), Vector3::new(3.0, -1.0, 0.5), Vector3::new(-1.0, 3.0, 0.25)]);
+        let (decal, base) = (SolidShader::red(), SolidShader(Vector4::new(0.0, 0.0, 1.0, 1.0)));
+
+        for (depth_bias, slope_scaled_depth_bias) in [(1e-4, 0.0), (0.0, 1.0)] {
+            for decal_first in [true, false] {
+                let mut rasterizer = Rasterizer::new(8, 8, RasterOptions::default());
+                let mut draw = |biased: bool| {
+                    let options = rasterizer.options_mut();
+                    options.depth_bias = if biased { depth_bias } else { 0.0 };
+                    options.slope_scaled_depth_bias = if biased { slope_scaled_depth_bias } else { 0.0 };
+                    rasterizer.draw_mesh(&surface, if biased { &decal } else { &base }).unwrap();
+                };
+                draw(decal_first);
+                draw(!decal_first);
+
+                for (x, y) in (0..8).flat_map(|y| (0..8).map(move |x| (x, y))) {
+                    assert_eq!(rasterizer.pixel_at(x, y), Some(Vector3::x()), "({x}, {y}) biasing by {depth_bias} and {slope_scaled_depth_bias} times the slope, decal first: {decal_first}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn homogeneous_positions_draw_as_their_divided_points() {
+        let corners = [(Vector3::new(-0.75, -0.5, 0.25), Vector3::zeros()), (Vector3::new(0.75, -0.5, 0.0), Vector3::x()), (Vector3::new(0.0, 0.75, -0.25), Vector3::y())];
+        // Differing ws would also skew the interpolation if they reached the vertex shader
+        let render = |ws: [f32; 3]| {
+            let vertices = [0, 1, 2].map(|index| {
+                let ((position, uv), w) = (corners[index], ws[index]);
+                Vertex::from_pos_tex((position * w).push(w), uv)
+            });
+            let face = Face::new(vertices);
+            let mut rasterizer = Rasterizer::new(16, 16, RasterOptions::default());
+            rasterizer.storage_mut().set_mat4(0, Matrix4::identity());
+            rasterizer.draw_mesh(&Mesh::new(None, vec![face]), &UvShader).unwrap();
+
+            let mut buffer = vec![0; 16 * 16];
+            rasterizer.render_to_buffer(&mut buffer);
+            buffer
+        };
+
+        let at_w_one = render([1.0; 3]);
+        assert!(at_w_one.iter().any(|&pixel| pixel != 0));
+        assert_eq!(render([2.0; 3]), at_w_one);
+        assert_eq!(render([2.0, 1.0, 4.0]), at_w_one);
+    }
+}
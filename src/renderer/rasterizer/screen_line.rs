@@ -0,0 +1,106 @@
+use nalgebra::{Matrix4, Vector2, Vector4};
+use crate::shader::VertexShaderOutputVariables;
+
+// Cohen-Sutherland outcode bits, which sides of the rectangle a point is past
+const LEFT: u8 = 1;
+const RIGHT: u8 = 2;
+const TOP: u8 = 4;
+const BOTTOM: u8 = 8;
+
+pub struct ScreenLine<'a> {
+    pub vertex_outputs: [&'a VertexShaderOutputVariables; 2],
+    pub screen_coords_pre_perspective: [Vector4<f32>; 2],
+    pub screen_coords_2d: [Vector2<f32>; 2],
+    // Pulls the line's fragments towards the near plane by this much in normalized device depth
+    pub depth_bias: f32,
+}
+
+impl<'a> ScreenLine<'a> {
+    pub fn new(vertex_outputs: [&'a VertexShaderOutputVariables; 2], viewport: &Matrix4<f32>) -> Self {
+        let screen_coords_pre_perspective = vertex_outputs.map(|output| viewport * output.position);
+        let screen_coords_2d = screen_coords_pre_perspective.map(|coords| coords.xy() / coords.w);
+
+        Self {
+            vertex_outputs,
+            screen_coords_pre_perspective,
+            screen_coords_2d,
+            depth_bias: 0.0,
+        }
+    }
+
+    pub fn with_depth_bias(mut self, depth_bias: f32) -> Self {
+        self.depth_bias = depth_bias;
+        self
+    }
+
+    // Cohen-Sutherland against the rectangle from min to max, as the range of t from the start
+    // to the end of the line that's inside it, None when none of it is
+    pub fn clip_to_rect(&self, min: Vector2<f32>, max: Vector2<f32>) -> Option<(f32, f32)> {
+        let [start, end] = self.screen_coords_2d;
+        let delta = end - start;
+        let point = |t: f32| start + delta * t;
+
+        let mut ts = [0.0, 1.0];
+        let mut codes = ts.map(|t| Self::outcode(point(t), min, max));
+        let mut clipped_sides = [0, 0];
+
+        loop {
+            if codes[0] | codes[1] == 0 { return Some((ts[0], ts[1])) }
+            if codes[0] & codes[1] != 0 { return None }
+
+            // Move an outside endpoint onto the first side it's past. Sides an endpoint has been
+            // moved onto stay cleared so rounding just past one can't clip against it again.
+            let end_index = if codes[0] != 0 { 0 } else { 1 };
+            let code = codes[end_index];
+            let (side, t) = if code & LEFT != 0 {
+                (LEFT, (min.x - start.x) / delta.x)
+            } else if code & RIGHT != 0 {
+                (RIGHT, (max.x - start.x) / delta.x)
+            } else if code & TOP != 0 {
+                (TOP, (min.y - start.y) / delta.y)
+            } else {
+                (BOTTOM, (max.y - start.y) / delta.y)
+            };
+
+            ts[end_index] = t;
+            clipped_sides[end_index] |= side;
+            codes[end_index] = Self::outcode(point(t), min, max) & !clipped_sides[end_index];
+        }
+    }
+
+    fn outcode(point: Vector2<f32>, min: Vector2<f32>, max: Vector2<f32>) -> u8 {
+        let mut code = 0;
+        if point.x < min.x { code |= LEFT } else if point.x > max.x { code |= RIGHT }
+        if point.y < min.y { code |= TOP } else if point.y > max.y { code |= BOTTOM }
+        code
+    }
+
+    pub fn y_range(&self) -> (f32, f32) {
+        let [a, b] = self.screen_coords_2d;
+        (a.y.min(b.y), a.y.max(b.y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix4, Vector2, Vector4};
+    use crate::shader::VertexShaderOutputVariables;
+    use super::ScreenLine;
+
+    // With an identity viewport the positions are the screen coordinates
+    fn clip(start: Vector2<f32>, end: Vector2<f32>) -> Option<(f32, f32)> {
+        let [start, end] = [start, end].map(|point| VertexShaderOutputVariables::new(Vector4::new(point.x, point.y, 0.0, 1.0)));
+        ScreenLine::new([&start, &end], &Matrix4::identity()).clip_to_rect(Vector2::zeros(), Vector2::new(20.0, 20.0))
+    }
+
+    #[test]
+    fn line_crossing_the_left_edge_starts_on_it() {
+        assert_eq!(clip(Vector2::new(-10.0, 5.0), Vector2::new(10.0, 15.0)), Some((0.5, 1.0)));
+        assert_eq!(clip(Vector2::new(10.0, 15.0), Vector2::new(-10.0, 5.0)), Some((0.0, 0.5)));
+    }
+
+    #[test]
+    fn line_left_of_the_rect_is_dropped() {
+        assert_eq!(clip(Vector2::new(-10.0, 5.0), Vector2::new(-1.0, 15.0)), None);
+    }
+}
@@ -0,0 +1,58 @@
+use nalgebra::{Matrix4, Vector2, Vector4};
+use crate::renderer::rasterizer::depth::DepthMode;
+use crate::shader::VertexShaderOutputVariables;
+
+pub struct ScreenTriangle<'a> {
+    pub vertex_outputs: [&'a VertexShaderOutputVariables; 3],
+    pub screen_coords_pre_perspective: [Vector4<f32>; 3],
+    pub screen_coords_2d: [Vector2<f32>; 3],
+}
+
+impl<'a> ScreenTriangle<'a> {
+    pub fn new(vertex_outputs: [&'a VertexShaderOutputVariables; 3], viewport: &Matrix4<f32>) -> Self {
+        let screen_coords_pre_perspective = vertex_outputs.map(|output| viewport * output.position);
+        let screen_coords_2d = screen_coords_pre_perspective.map(|coords| coords.xy() / coords.w);
+
+        Self {
+            vertex_outputs,
+            screen_coords_pre_perspective,
+            screen_coords_2d,
+        }
+    }
+
+    pub fn vertex_positions(&self) -> [Vector4<f32>; 3] {
+        self.vertex_outputs.map(|output| output.position)
+    }
+
+    // Smaller is nearer, in the depth mode's distance order
+    pub fn nearest_distance(&self, depth_mode: DepthMode) -> f32 {
+        self.vertex_outputs.iter()
+            .map(|output| depth_mode.distance_key(output.position.z))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    // In square pixels, regardless of winding
+    pub fn area(&self) -> f32 {
+        let [a, b, c] = self.screen_coords_2d;
+        ((b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y)).abs() / 2.0
+    }
+
+    // The larger of how fast normalized device depth changes per pixel in x and in y,
+    // zero for a triangle with no area
+    pub fn max_depth_slope(&self) -> f32 {
+        let [a, b, c] = self.screen_coords_2d;
+        let [depth_a, depth_b, depth_c] = self.screen_coords_pre_perspective.map(|coords| coords.z / coords.w);
+
+        let double_area = (b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y);
+        if double_area.abs() <= f32::EPSILON { return 0.0 }
+
+        let slope_x = ((depth_b - depth_a) * (c.y - a.y) - (depth_c - depth_a) * (b.y - a.y)) / double_area;
+        let slope_y = ((depth_c - depth_a) * (b.x - a.x) - (depth_b - depth_a) * (c.x - a.x)) / double_area;
+        slope_x.abs().max(slope_y.abs())
+    }
+
+    pub fn y_range(&self) -> (f32, f32) {
+        let ys = self.screen_coords_2d.map(|coords| coords.y);
+        (ys[0].min(ys[1]).min(ys[2]), ys[0].max(ys[1]).max(ys[2]))
+    }
+}
@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Counted since the last clear while RasterOptions::collect_stats is set
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RasterStats {
+    pub meshes_culled: u64,
+    pub triangles_submitted: u64,
+    // Entirely outside the view frustum
+    pub triangles_clipped: u64,
+    // Back faces and those under min_triangle_area, counted after clipping so a clipped triangle can count more than once
+    pub triangles_culled: u64,
+    pub fragments_shaded: u64,
+    // Discarded by the fragment shader or the alpha cutoff, or with too little alpha to show
+    pub fragments_discarded: u64,
+}
+
+impl RasterStats {
+    pub(crate) fn add_fragments(&mut self, counters: &FragmentCounters) {
+        self.fragments_shaded += counters.shaded.load(Ordering::Relaxed);
+        self.fragments_discarded += counters.discarded.load(Ordering::Relaxed);
+    }
+}
+
+// Shared by the chunks while drawing, then folded into RasterStats
+#[derive(Default)]
+pub(crate) struct FragmentCounters {
+    shaded: AtomicU64,
+    discarded: AtomicU64,
+}
+
+impl FragmentCounters {
+    pub fn count_shaded(&self) {
+        self.shaded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count_discarded(&self) {
+        self.discarded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Shaded and not discarded
+    pub fn passed(&self) -> u64 {
+        self.shaded.load(Ordering::Relaxed) - self.discarded.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,91 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StencilFunc {
+    Never,
+    Always,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    Increment,
+    Decrement,
+    Invert,
+}
+
+// Comparisons are `reference <func> stored`, as in OpenGL
+#[derive(Copy, Clone, Debug)]
+pub struct StencilOptions {
+    pub func: StencilFunc,
+    pub reference: u8,
+    pub fail_op: StencilOp,
+    pub depth_fail_op: StencilOp,
+    pub pass_op: StencilOp,
+}
+
+impl StencilOptions {
+    pub fn test(&self, stored: u8) -> bool {
+        let reference = self.reference;
+        match self.func {
+            StencilFunc::Never => false,
+            StencilFunc::Always => true,
+            StencilFunc::Less => reference < stored,
+            StencilFunc::LessEqual => reference <= stored,
+            StencilFunc::Greater => reference > stored,
+            StencilFunc::GreaterEqual => reference >= stored,
+            StencilFunc::Equal => reference == stored,
+            StencilFunc::NotEqual => reference != stored,
+        }
+    }
+
+    pub fn apply(&self, op: StencilOp, stored: &mut u8) {
+        *stored = match op {
+            StencilOp::Keep => *stored,
+            StencilOp::Zero => 0,
+            StencilOp::Replace => self.reference,
+            StencilOp::Increment => stored.saturating_add(1),
+            StencilOp::Decrement => stored.saturating_sub(1),
+            StencilOp::Invert => !*stored,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Vector3, Vector4};
+    use crate::mesh::Mesh;
+    use crate::renderer::rasterizer::{RasterOptions, Rasterizer};
+    use crate::renderer::rasterizer::test_support::{drawn_pixels, full_screen_triangle, SolidShader};
+    use super::{StencilFunc, StencilOp, StencilOptions};
+
+    fn stencil(func: StencilFunc, pass_op: StencilOp) -> Option<StencilOptions> {
+        Some(StencilOptions { func, reference: 1, fail_op: StencilOp::Keep, depth_fail_op: StencilOp::Keep, pass_op })
+    }
+
+    #[test]
+    fn equal_only_draws_inside_the_written_region() {
+        let ndc = |x: f32, y: f32| Vector3::new(2.0 * x / 8.0 - 1.0, 1.0 - 2.0 * y / 8.0, 0.0);
+        // From (1.5, 1.5) to (5.5, 4.5) in pixels, covering the centres of columns 2 to 5 and rows 2 to 4
+        let region = Mesh::quad_spanning(ndc(3.5, 3.0), Vector3::x(), Vector3::y() * 0.75);
+
+        let mut rasterizer = Rasterizer::new(8, 8, RasterOptions { stencil: stencil(StencilFunc::Always, StencilOp::Replace), ..Default::default() });
+        // Fully transparent, so it only writes the stencil
+        rasterizer.draw_mesh(&region, &SolidShader(Vector4::zeros())).unwrap();
+
+        rasterizer.options_mut().stencil = stencil(StencilFunc::Equal, StencilOp::Keep);
+        rasterizer.draw_mesh(&full_screen_triangle(0.0), &SolidShader::red()).unwrap();
+
+        let drawn = drawn_pixels(&rasterizer);
+        for (x, y) in (0..8).flat_map(|y| (0..8).map(move |x| (x, y))) {
+            let inside = (2..=5).contains(&x) && (2..=4).contains(&y);
+            assert_eq!(drawn[y * 8 + x], inside, "({x}, {y})");
+        }
+    }
+}
@@ -1,26 +1,132 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use nalgebra::Matrix4;
+use crate::material::Material;
+use crate::renderer::rasterizer::cube_map::CubeMap;
 use crate::renderer::rasterizer::texture2d::Texture2D;
 
+// Storage slots a shader reads, texture slots are checked through the bound texture indices
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct Bindings {
+    pub mat4s: Vec<usize>,
+    pub texture2ds: Vec<usize>,
+    pub cube_maps: Vec<usize>,
+    pub f32s: Vec<usize>,
+}
+
+impl Bindings {
+    pub fn with_mat4s(mut self, slots: &[usize]) -> Self {
+        self.mat4s.extend_from_slice(slots);
+        self
+    }
+
+    pub fn with_texture2ds(mut self, slots: &[usize]) -> Self {
+        self.texture2ds.extend_from_slice(slots);
+        self
+    }
+
+    pub fn with_cube_maps(mut self, slots: &[usize]) -> Self {
+        self.cube_maps.extend_from_slice(slots);
+        self
+    }
+
+    pub fn with_f32s(mut self, slots: &[usize]) -> Self {
+        self.f32s.extend_from_slice(slots);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mat4s.is_empty() && self.texture2ds.is_empty() && self.cube_maps.is_empty() && self.f32s.is_empty()
+    }
+}
+
+// The required bindings Storage couldn't satisfy, nothing was drawn
+#[derive(Debug)]
+pub struct BindingError {
+    pub missing: Bindings,
+}
+
+impl Display for BindingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let missing = &self.missing;
+        let groups = [("mat4s", &missing.mat4s), ("texture slots", &missing.texture2ds), ("cube maps", &missing.cube_maps), ("f32s", &missing.f32s)];
+
+        write!(f, "Shader bindings are missing:")?;
+        for (name, slots) in groups.into_iter().filter(|(_, slots)| !slots.is_empty()) {
+            write!(f, " {name} {slots:?}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for BindingError {}
+
 #[derive(Default)]
 pub struct Storage {
     textures2d: Vec<Texture2D>,
     textures2d_indices: Vec<usize>,
+    cube_maps: Vec<CubeMap>,
     f32s: Vec<f32>,
     mat4s: Vec<Matrix4<f32>>,
+    material: Material,
 }
 
 impl Storage {
+    pub fn validate(&self, bindings: &Bindings) -> Result<(), BindingError> {
+        let missing = Bindings {
+            mat4s: bindings.mat4s.iter().copied().filter(|slot| self.try_get_mat4(*slot).is_none()).collect(),
+            texture2ds: bindings.texture2ds.iter().copied().filter(|slot| self.try_get_texture2d(*slot).is_none()).collect(),
+            cube_maps: bindings.cube_maps.iter().copied().filter(|slot| self.try_get_cube_map(*slot).is_none()).collect(),
+            f32s: bindings.f32s.iter().copied().filter(|slot| self.try_get_f32(*slot).is_none()).collect(),
+        };
+
+        if missing.is_empty() { Ok(()) } else { Err(BindingError { missing }) }
+    }
+
     pub fn set_texture2ds(&mut self, textures: Vec<Texture2D>) {
         self.textures2d = textures;
     }
 
-    pub fn set_texture2d_indices(&mut self, indices: Vec<usize>) {
-        self.textures2d_indices = indices;
+    // Binds textures to the slots shaders sample for the next draws, slot n reads
+    // textures2d[indices[n]], so a draw with &[3, 7] samples texture 3 as slot 0 and 7 as slot 1
+    pub fn set_texture2d_indices(&mut self, indices: &[usize]) {
+        self.textures2d_indices.clear();
+        self.textures2d_indices.extend_from_slice(indices);
+    }
+
+    // Rebinds a single slot, leaving the others. Slots added to reach it are left unbound.
+    pub fn set_texture2d_index(&mut self, slot: usize, index: usize) {
+        if slot >= self.textures2d_indices.len() {
+            self.textures2d_indices.resize(slot + 1, usize::MAX);
+        }
+        self.textures2d_indices[slot] = index;
     }
 
+    // The get_* methods panic with the slot and lengths involved, the try_get_* variants return None instead.
+    // Both only cost the bounds checks plain indexing already does.
     pub fn get_texture2d(&self, index: usize) -> &Texture2D {
-        let index = self.textures2d_indices[index];
-        &self.textures2d[index]
+        self.try_get_texture2d(index).unwrap_or_else(|| match self.textures2d_indices.get(index) {
+            Some(&usize::MAX) => panic!("Texture slot {index} is unbound"),
+            Some(texture) => panic!("Texture slot {index} points at texture {texture} but only {} textures are set", self.textures2d.len()),
+            None => panic!("Texture slot {index} is out of range, only {} texture indices are set", self.textures2d_indices.len()),
+        })
+    }
+
+    pub fn try_get_texture2d(&self, index: usize) -> Option<&Texture2D> {
+        self.textures2d.get(*self.textures2d_indices.get(index)?)
+    }
+
+    pub fn set_cube_maps(&mut self, cube_maps: Vec<CubeMap>) {
+        self.cube_maps = cube_maps;
+    }
+
+    pub fn get_cube_map(&self, index: usize) -> &CubeMap {
+        self.try_get_cube_map(index)
+            .unwrap_or_else(|| panic!("Cube map {index} is out of range, only {} cube maps are set", self.cube_maps.len()))
+    }
+
+    pub fn try_get_cube_map(&self, index: usize) -> Option<&CubeMap> {
+        self.cube_maps.get(index)
     }
 
     pub fn set_f32s(&mut self, f32s: Vec<f32>) {
@@ -28,15 +134,75 @@ impl Storage {
     }
 
     pub fn get_f32(&self, index: usize) -> f32 {
-        self.f32s[index]
+        self.try_get_f32(index)
+            .unwrap_or_else(|| panic!("f32 {index} is out of range, only {} f32s are set", self.f32s.len()))
+    }
+
+    pub fn try_get_f32(&self, index: usize) -> Option<f32> {
+        self.f32s.get(index).copied()
+    }
+
+    pub fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    pub fn get_material(&self) -> &Material {
+        &self.material
     }
 
     pub fn set_mat4s(&mut self, mat4s: Vec<Matrix4<f32>>) {
         self.mat4s = mat4s;
     }
 
+    // Replaces a single mat4 in place, leaving the others. Any added to reach it are identity.
+    pub fn set_mat4(&mut self, index: usize, mat4: Matrix4<f32>) {
+        if index >= self.mat4s.len() {
+            self.mat4s.resize(index + 1, Matrix4::identity());
+        }
+        self.mat4s[index] = mat4;
+    }
+
     pub fn get_mat4(&self, index: usize) -> &Matrix4<f32> {
-        &self.mat4s[index]
+        self.try_get_mat4(index)
+            .unwrap_or_else(|| panic!("Mat4 {index} is out of range, only {} mat4s are set", self.mat4s.len()))
+    }
+
+    pub fn try_get_mat4(&self, index: usize) -> Option<&Matrix4<f32>> {
+        self.mat4s.get(index)
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Matrix4;
+    use crate::renderer::rasterizer::texture2d::Texture2D;
+    use super::Storage;
+
+    #[test]
+    fn set_mat4_leaves_other_slots_alone() {
+        let scale = |factor: f32| Matrix4::new_scaling(factor);
+        let mut storage = Storage::default();
+        storage.set_mat4s(vec![scale(1.0), scale(2.0), scale(3.0)]);
+
+        storage.set_mat4(1, scale(4.0));
+        storage.set_mat4(5, scale(5.0));
+
+        let mat4s = (0..6).map(|index| *storage.get_mat4(index)).collect::<Vec<_>>();
+        assert_eq!(mat4s, [scale(1.0), scale(4.0), scale(3.0), Matrix4::identity(), Matrix4::identity(), scale(5.0)]);
+    }
+
+    #[test]
+    fn set_texture2d_index_leaves_other_slots_alone() {
+        // Told apart by their red channel
+        let mut storage = Storage::default();
+        storage.set_texture2ds((1..=3).map(|red| Texture2D::from_rgba(1, 1, vec![red, 0, 0, 255]).unwrap()).collect());
+        storage.set_texture2d_indices(&[0, 1]);
+
+        storage.set_texture2d_index(0, 2);
+        storage.set_texture2d_index(3, 0);
+
+        let reds = (0..4).map(|slot| storage.try_get_texture2d(slot).map(|texture| texture.fetch(0, 0).x)).collect::<Vec<_>>();
+        assert_eq!(reds, [Some(3), Some(2), None, Some(1)]);
+    }
+}
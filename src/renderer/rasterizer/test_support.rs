@@ -0,0 +1,70 @@
+use nalgebra::{Matrix4, Vector3, Vector4};
+use crate::mesh::{Face, Mesh, Vertex};
+use crate::renderer::post_processor::PostProcessorOptions;
+use crate::renderer::rasterizer::{RasterOptions, Rasterizer};
+use crate::renderer::RendererOptions;
+use crate::shader::{FragmentOutput, FragmentShaderInputVariables, Shader, VertexShaderInputVariables, VertexShaderOutputVariables};
+
+// Shared by the rasterizer's tests. Transforms by mat4 0 when it's set, otherwise positions are
+// taken as clip space, and fills with one colour.
+pub(crate) struct SolidShader(pub Vector4<f32>);
+
+impl SolidShader {
+    pub fn red() -> Self {
+        Self(Vector4::new(1.0, 0.0, 0.0, 1.0))
+    }
+}
+
+impl Shader for SolidShader {
+    fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+        let transform = input_vars.storage.try_get_mat4(0).copied().unwrap_or_else(Matrix4::identity);
+        VertexShaderOutputVariables::new(transform * input_vars.position)
+    }
+
+    fn fragment(&self, _input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        FragmentOutput::Color(self.0)
+    }
+}
+
+pub(crate) fn triangle(positions: [Vector3<f32>; 3]) -> Mesh {
+    Mesh::new(None, vec![Face::new(positions.map(|position| Vertex::from_pos(position.push(1.0))))])
+}
+
+// Covers the whole view at clip space depth z, when drawn without a transform
+pub(crate) fn full_screen_triangle(z: f32) -> Mesh {
+    triangle([Vector3::new(-1.0, -1.0, z), Vector3::new(3.0, -1.0, z), Vector3::new(-1.0, 3.0, z)])
+}
+
+// Row by row, packed as render_to_buffer packs them
+pub(crate) fn resolved_pixels(rasterizer: &Rasterizer) -> Vec<u32> {
+    let framebuffer = rasterizer.framebuffer();
+    let mut buffer = vec![0; framebuffer.width() * framebuffer.height()];
+    rasterizer.render_to_buffer(&mut buffer);
+    buffer
+}
+
+pub(crate) fn resolved_pixel(rasterizer: &Rasterizer, x: usize, y: usize) -> u32 {
+    resolved_pixels(rasterizer)[y * rasterizer.framebuffer().width() + x]
+}
+
+// Row by row, whether anything but the default black background shows
+pub(crate) fn drawn_pixels(rasterizer: &Rasterizer) -> Vec<bool> {
+    resolved_pixels(rasterizer).into_iter().map(|pixel| pixel != 0).collect()
+}
+
+// Every post processing effect off, rendering at full size
+pub(crate) fn plain_renderer_options() -> RendererOptions {
+    RendererOptions {
+        raster_options: RasterOptions::default(),
+        post_processor_options: PostProcessorOptions {
+            fxaa: false,
+            sharpen: None,
+            color_filter: None,
+            chromatic_aberration: None,
+            outline: None,
+            debug_depth: false,
+        },
+        render_scale: 1.0,
+        supersample: 1,
+    }
+}
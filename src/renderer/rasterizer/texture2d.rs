@@ -1,23 +1,250 @@
-use image::RgbaImage;
-use nalgebra::Vector4;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use image::{GrayImage, ImageError, RgbImage, RgbaImage};
+use nalgebra::{Vector2, Vector4};
+
+#[derive(Debug)]
+pub enum TextureError {
+    SizeMismatch { expected: usize, actual: usize },
+    // Sampling has no texel to clamp to with a width or height of zero
+    ZeroSize { width: usize, height: usize },
+    Io { path: PathBuf, source: io::Error },
+    // The extension or contents aren't a format the image crate can decode
+    UnsupportedFormat { path: PathBuf, source: ImageError },
+    Decode { path: PathBuf, source: ImageError },
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::SizeMismatch { expected, actual } => {
+                write!(f, "Texture data has {actual} elements but its dimensions require {expected}")
+            }
+            TextureError::ZeroSize { width, height } => write!(f, "Texture is {width}x{height}, it needs at least one texel"),
+            TextureError::Io { path, source } => write!(f, "Failed to read texture {}: {source}", path.display()),
+            TextureError::UnsupportedFormat { path, source } => write!(f, "Unsupported texture format {}: {source}", path.display()),
+            TextureError::Decode { path, source } => write!(f, "Failed to decode texture {}: {source}", path.display()),
+        }
+    }
+}
+
+impl Error for TextureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TextureError::SizeMismatch { .. } | TextureError::ZeroSize { .. } => None,
+            TextureError::Io { source, .. } => Some(source),
+            TextureError::UnsupportedFormat { source, .. } | TextureError::Decode { source, .. } => Some(source),
+        }
+    }
+}
 
 pub struct Texture2D {
     pixels: Vec<Vector4<u8>>,
     width: usize,
     height: usize,
+    // Whether v runs up from the bottom row as with OBJ and OpenGL uvs, true by default.
+    // Off for textures authored with v running down from the top.
+    flip_v: bool,
+    // Successively halved copies, empty until generate_mipmaps is called
+    mips: Vec<Texture2D>,
+}
+
+#[derive(Copy, Clone)]
+pub struct SamplerOptions {
+    // Added to the chosen mip level, negative is sharper and positive blurrier
+    pub mip_bias: f32,
+    // Most samples taken along the longer axis of a stretched footprint, 1 disables anisotropic filtering
+    pub max_anisotropy: u32,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            mip_bias: 0.0,
+            max_anisotropy: 1,
+        }
+    }
 }
 
 impl Texture2D {
-    pub fn sample(&self, u: f32, v: f32) -> Vector4<f32> {
-        let u = (u * (self.width - 1) as f32) as usize;
-        let v = self.height - (v * (self.height - 1) as f32) as usize - 1;
+    // The format is detected from the extension, falling back to the file's contents
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TextureError> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(|source| {
+            let path = path.to_path_buf();
+            match source {
+                ImageError::IoError(source) => TextureError::Io { path, source },
+                ImageError::Unsupported(_) => TextureError::UnsupportedFormat { path, source },
+                _ => TextureError::Decode { path, source },
+            }
+        })?;
 
-        let u = u.min(self.width - 1);
-        let v = v.min(self.height - 1);
+        let image = image.to_rgba8();
+        Self::check_size(image.width() as usize, image.height() as usize)?;
+        Ok(image.into())
+    }
 
-        let u8_pixel = self.pixels[v * self.width + u];
+    // Tightly packed rgba bytes, row by row from the top
+    pub fn from_rgba(width: usize, height: usize, data: Vec<u8>) -> Result<Self, TextureError> {
+        Self::check_size(width, height)?;
+        if data.len() != width * height * 4 {
+            return Err(TextureError::SizeMismatch { expected: width * height * 4, actual: data.len() });
+        }
+
+        Ok(Self {
+            pixels: data.chunks_exact(4).map(|p| Vector4::new(p[0], p[1], p[2], p[3])).collect(),
+            width,
+            height,
+            flip_v: true,
+            mips: Vec::new(),
+        })
+    }
+
+    // Colours in the 0.0 to 1.0 range, anything outside is clamped
+    pub fn from_rgba_f32(width: usize, height: usize, data: Vec<Vector4<f32>>) -> Result<Self, TextureError> {
+        Self::check_size(width, height)?;
+        if data.len() != width * height {
+            return Err(TextureError::SizeMismatch { expected: width * height, actual: data.len() });
+        }
+
+        Ok(Self {
+            pixels: data.into_iter().map(|p| p.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)).collect(),
+            width,
+            height,
+            flip_v: true,
+            mips: Vec::new(),
+        })
+    }
+
+    fn check_size(width: usize, height: usize) -> Result<(), TextureError> {
+        if width == 0 || height == 0 {
+            return Err(TextureError::ZeroSize { width, height });
+        }
+        Ok(())
+    }
+
+    pub fn sample(&self, u: f32, v: f32) -> Vector4<f32> {
+        let u8_pixel = self.pixels[self.texel_index(u, v)];
         Vector4::new(u8_pixel.x as f32, u8_pixel.y as f32, u8_pixel.z as f32, u8_pixel.w as f32) / 255.0
     }
+
+    pub fn flip_v(&self) -> bool {
+        self.flip_v
+    }
+
+    // Applies to the mip levels too, whether or not they've been generated yet
+    pub fn set_flip_v(&mut self, flip_v: bool) {
+        self.flip_v = flip_v;
+        for mip in &mut self.mips {
+            mip.flip_v = flip_v;
+        }
+    }
+
+    pub fn with_flip_v(mut self, flip_v: bool) -> Self {
+        self.set_flip_v(flip_v);
+        self
+    }
+
+    // The exact texel with no filtering, for lookup tables and ids. Ignoring flip_v, y counts
+    // rows from the top, so (0, 0) is the first texel of the data. Panics outside the texture.
+    pub fn fetch(&self, x: usize, y: usize) -> Vector4<u8> {
+        self.try_fetch(x, y)
+            .unwrap_or_else(|| panic!("Texel ({x}, {y}) is out of range for a {}x{} texture", self.width, self.height))
+    }
+
+    pub fn try_fetch(&self, x: usize, y: usize) -> Option<Vector4<u8>> {
+        if x >= self.width || y >= self.height { return None }
+
+        Some(self.pixels[y * self.width + x])
+    }
+
+    // Picks the mip level from the uv change per screen pixel, as given by get_derivatives.
+    // Without mipmaps this samples the full size texture, only spreading anisotropic samples.
+    pub fn sample_grad(&self, uv: Vector2<f32>, ddx: Vector2<f32>, ddy: Vector2<f32>, options: &SamplerOptions) -> Vector4<f32> {
+        let size = Vector2::new(self.width as f32, self.height as f32);
+        let footprint_x = ddx.component_mul(&size).norm();
+        let footprint_y = ddy.component_mul(&size).norm();
+
+        let (major, minor, major_axis) = if footprint_x >= footprint_y {
+            (footprint_x, footprint_y, ddx)
+        } else {
+            (footprint_y, footprint_x, ddy)
+        };
+
+        // Samples spread along the major axis cover its length so the level only has to cover the rest
+        let samples = (major / minor.max(f32::EPSILON)).ceil().clamp(1.0, options.max_anisotropy.max(1) as f32);
+        let lod = (major / samples).max(f32::EPSILON).log2() + options.mip_bias;
+        let level = lod.round().clamp(0.0, self.mips.len() as f32) as usize;
+        let texture = if level == 0 { self } else { &self.mips[level - 1] };
+
+        let samples = samples as usize;
+        let mut colour = Vector4::zeros();
+        for sample in 0..samples {
+            let offset = (sample as f32 + 0.5) / samples as f32 - 0.5;
+            let sample_uv = uv + major_axis * offset;
+            colour += texture.sample(sample_uv.x, sample_uv.y);
+        }
+        colour / samples as f32
+    }
+
+    // Box filters down to 1x1, costing a third more memory
+    pub fn generate_mipmaps(&mut self) {
+        let mut mips: Vec<Texture2D> = Vec::new();
+
+        loop {
+            let previous = mips.last().unwrap_or(self);
+            if previous.width == 1 && previous.height == 1 { break }
+
+            let mip = previous.downsampled();
+            mips.push(mip);
+        }
+
+        self.mips = mips;
+    }
+
+    fn downsampled(&self) -> Texture2D {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                // Odd edges clamp, so their last row or column is averaged with itself
+                let xs = [(x * 2).min(self.width - 1), (x * 2 + 1).min(self.width - 1)];
+                let ys = [(y * 2).min(self.height - 1), (y * 2 + 1).min(self.height - 1)];
+
+                let sum = ys.iter()
+                    .flat_map(|&y| xs.map(|x| self.pixels[y * self.width + x].map(|c| c as u32)))
+                    .fold(Vector4::zeros(), |sum, texel| sum + texel);
+
+                pixels.push(sum.map(|c| ((c + 2) / 4) as u8));
+            }
+        }
+
+        Texture2D {
+            pixels,
+            width,
+            height,
+            flip_v: self.flip_v,
+            mips: Vec::new(),
+        }
+    }
+
+    pub fn sample_channel(&self, u: f32, v: f32, channel: usize) -> f32 {
+        self.pixels[self.texel_index(u, v)][channel] as f32 / 255.0
+    }
+
+    // Texel x covers u from x / width to (x + 1) / width, so (x + 0.5) / width is its centre and
+    // every texel gets an equal share of 0.0 to 1.0. Outside that range clamps to the edge texels.
+    fn texel_index(&self, u: f32, v: f32) -> usize {
+        let x = ((u * self.width as f32).floor().max(0.0) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32).floor().max(0.0) as usize).min(self.height - 1);
+        let y = if self.flip_v { self.height - 1 - y } else { y };
+
+        y * self.width + x
+    }
 }
 
 impl From<RgbaImage> for Texture2D {
@@ -26,6 +253,91 @@ impl From<RgbaImage> for Texture2D {
             pixels: value.pixels().map(|p| Vector4::new(p[0], p[1], p[2], p[3])).collect(),
             width: value.width() as usize,
             height: value.height() as usize,
+            flip_v: true,
+            mips: Vec::new(),
+        }
+    }
+}
+
+// Fully opaque
+impl From<RgbImage> for Texture2D {
+    fn from(value: RgbImage) -> Self {
+        Self {
+            pixels: value.pixels().map(|p| Vector4::new(p[0], p[1], p[2], 255)).collect(),
+            width: value.width() as usize,
+            height: value.height() as usize,
+            flip_v: true,
+            mips: Vec::new(),
+        }
+    }
+}
+
+// The luma is replicated into rgb so any channel can be sampled, alpha is opaque
+impl From<GrayImage> for Texture2D {
+    fn from(value: GrayImage) -> Self {
+        Self {
+            pixels: value.pixels().map(|p| Vector4::new(p[0], p[0], p[0], 255)).collect(),
+            width: value.width() as usize,
+            height: value.height() as usize,
+            flip_v: true,
+            mips: Vec::new(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use super::{Texture2D, TextureError};
+
+    #[test]
+    fn flip_v_picks_which_row_v_starts_from() {
+        let (red, blue) = ([255, 0, 0, 255], [0, 0, 255, 255]);
+        let texture = || Texture2D::from_rgba(1, 2, [red, blue].concat()).unwrap();
+        let (red, blue) = (Vector4::new(1.0, 0.0, 0.0, 1.0), Vector4::new(0.0, 0.0, 1.0, 1.0));
+
+        // On by default, v = 0 is the bottom row
+        let flipped = texture();
+        assert_eq!(flipped.sample(0.5, 0.25), blue);
+        assert_eq!(flipped.sample(0.5, 0.75), red);
+
+        let unflipped = texture().with_flip_v(false);
+        assert_eq!(unflipped.sample(0.5, 0.25), red);
+        assert_eq!(unflipped.sample(0.5, 0.75), blue);
+    }
+
+    #[test]
+    fn data_of_the_wrong_length_is_rejected() {
+        assert!(matches!(Texture2D::from_rgba(2, 2, vec![255; 12]), Err(TextureError::SizeMismatch { expected: 16, actual: 12 })));
+        assert!(matches!(Texture2D::from_rgba(1, 1, vec![255; 5]), Err(TextureError::SizeMismatch { expected: 4, actual: 5 })));
+        assert!(matches!(Texture2D::from_rgba_f32(2, 1, vec![Vector4::zeros()]), Err(TextureError::SizeMismatch { expected: 2, actual: 1 })));
+    }
+
+    #[test]
+    fn zero_sized_textures_are_rejected() {
+        assert!(matches!(Texture2D::from_rgba(0, 0, Vec::new()), Err(TextureError::ZeroSize { width: 0, height: 0 })));
+        assert!(matches!(Texture2D::from_rgba(2, 0, Vec::new()), Err(TextureError::ZeroSize { width: 2, height: 0 })));
+        assert!(matches!(Texture2D::from_rgba_f32(0, 3, Vec::new()), Err(TextureError::ZeroSize { width: 0, height: 3 })));
+    }
+
+    #[test]
+    fn texel_centres_sample_their_own_texel() {
+        let texels = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 255, 255]];
+        let texture = Texture2D::from_rgba(2, 2, texels.concat()).unwrap().with_flip_v(false);
+
+        for (index, texel) in texels.iter().enumerate() {
+            let (x, y) = (index % 2, index / 2);
+            let expected = Vector4::from(*texel).map(|channel| channel as f32 / 255.0);
+            assert_eq!(texture.sample((x as f32 + 0.5) / 2.0, (y as f32 + 0.5) / 2.0), expected, "Texel ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn single_texel_textures_sample_the_same_everywhere() {
+        let texture = Texture2D::from_rgba(1, 1, vec![255, 0, 255, 255]).unwrap();
+
+        for (u, v) in [(0.0, 0.0), (0.5, 0.5), (1.0, 1.0), (-3.0, 0.25), (0.75, 7.5)] {
+            assert_eq!(texture.sample(u, v), Vector4::new(1.0, 0.0, 1.0, 1.0), "uv ({u}, {v})");
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use nalgebra::{Matrix4, Vector3};
+use crate::mesh::{IndexedMesh, Mesh, QuadMesh};
+use crate::shader::Shader;
+
+pub enum Geometry<'a> {
+    Mesh(&'a Mesh),
+    Indexed(&'a IndexedMesh),
+    Quads(&'a QuadMesh),
+}
+
+impl Geometry<'_> {
+    pub fn bounding_box(&self) -> (Vector3<f32>, Vector3<f32>) {
+        match self {
+            Geometry::Mesh(mesh) => mesh.bounding_box(),
+            Geometry::Indexed(mesh) => mesh.bounding_box(),
+            Geometry::Quads(mesh) => mesh.bounding_box(),
+        }
+    }
+}
+
+impl<'a> From<&'a Mesh> for Geometry<'a> {
+    fn from(mesh: &'a Mesh) -> Self {
+        Geometry::Mesh(mesh)
+    }
+}
+
+impl<'a> From<&'a IndexedMesh> for Geometry<'a> {
+    fn from(mesh: &'a IndexedMesh) -> Self {
+        Geometry::Indexed(mesh)
+    }
+}
+
+impl<'a> From<&'a QuadMesh> for Geometry<'a> {
+    fn from(mesh: &'a QuadMesh) -> Self {
+        Geometry::Quads(mesh)
+    }
+}
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Transparency {
+    #[default]
+    Opaque,
+    // Blends with what's behind it, so it's drawn after every opaque item when sorting
+    Blend,
+}
+
+pub struct DrawItem<'a> {
+    pub geometry: Geometry<'a>,
+    pub transform: Matrix4<f32>,
+    pub texture_indices: Vec<usize>,
+    pub shader: &'a dyn Shader,
+    pub transparency: Transparency,
+}
+
+impl<'a> DrawItem<'a> {
+    pub fn new(geometry: impl Into<Geometry<'a>>, transform: Matrix4<f32>, texture_indices: Vec<usize>, shader: &'a dyn Shader) -> Self {
+        Self {
+            geometry: geometry.into(),
+            transform,
+            texture_indices,
+            shader,
+            transparency: Transparency::Opaque,
+        }
+    }
+
+    pub fn with_transparency(mut self, transparency: Transparency) -> Self {
+        self.transparency = transparency;
+        self
+    }
+
+    // Distance in front of the camera of the centre of the geometry's bounding box
+    fn view_depth(&self, view_projection: &Matrix4<f32>) -> f32 {
+        let (min, max) = self.geometry.bounding_box();
+        let centre = (view_projection * self.transform) * ((min + max) / 2.0).push(1.0);
+        // A perspective projection puts the view distance in w
+        centre.w
+    }
+}
+
+// Items are drawn in order unless sorting, each setting mat4 0 to the view projection, mat4 1 to its transform
+// and its texture indices first, the layout BasicShader reads. Items whose bounding box is outside the view
+// are skipped, whatever kind of geometry they hold.
+pub struct Scene<'a> {
+    pub view_projection: Matrix4<f32>,
+    pub items: Vec<DrawItem<'a>>,
+    // Draws opaque items front to back and then blended ones back to front by their bounding box
+    // centres instead of in order, off by default
+    pub sort: bool,
+}
+
+impl<'a> Scene<'a> {
+    pub fn new(view_projection: Matrix4<f32>) -> Self {
+        Self {
+            view_projection,
+            items: Vec::new(),
+            sort: false,
+        }
+    }
+
+    pub fn add(&mut self, item: DrawItem<'a>) {
+        self.items.push(item);
+    }
+
+    // The order draw_scene visits the items in, stable so ties keep the order they were added
+    pub fn draw_order(&self) -> Vec<&DrawItem<'a>> {
+        let mut items = self.items.iter().collect::<Vec<_>>();
+        if !self.sort { return items }
+
+        let sort_key = |item: &DrawItem| {
+            let depth = item.view_depth(&self.view_projection);
+            match item.transparency {
+                Transparency::Opaque => (0, depth),
+                Transparency::Blend => (1, -depth),
+            }
+        };
+        items.sort_by(|a, b| {
+            let (a_group, a_depth) = sort_key(a);
+            let (b_group, b_depth) = sort_key(b);
+            a_group.cmp(&b_group).then(a_depth.total_cmp(&b_depth))
+        });
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Translation3, Vector3};
+    use crate::camera::PerspectiveCamera;
+    use crate::mesh::Mesh;
+    use crate::shader::BasicShader;
+    use super::{DrawItem, Scene, Transparency};
+
+    #[test]
+    fn blended_items_are_drawn_back_to_front_after_opaque_ones() {
+        let quad = Mesh::quad();
+        let at_depth = |depth: f32| Translation3::from(Vector3::new(0.0, 0.0, -depth)).to_homogeneous();
+
+        let mut scene = Scene::new(PerspectiveCamera::perspective_projection(1.0, 1.0, 0.1, 100.0));
+        scene.sort = true;
+        scene.add(DrawItem::new(&quad, at_depth(2.0), vec![0], &BasicShader).with_transparency(Transparency::Blend));
+        scene.add(DrawItem::new(&quad, at_depth(5.0), vec![0], &BasicShader).with_transparency(Transparency::Blend));
+        scene.add(DrawItem::new(&quad, at_depth(8.0), vec![0], &BasicShader));
+
+        let depths = scene.draw_order().iter().map(|item| -item.transform.m34).collect::<Vec<_>>();
+        assert_eq!(depths, [8.0, 5.0, 2.0]);
+    }
+}
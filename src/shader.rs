@@ -1,11 +1,49 @@
-use nalgebra::{Vector2, Vector3, Vector4};
-use crate::renderer::rasterizer::storage::Storage;
+use std::marker::PhantomData;
+use std::ops::{Add, Mul};
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+use crate::renderer::rasterizer::depth::DepthMode;
+use crate::renderer::rasterizer::storage::{Bindings, Storage};
 
 pub trait Shader : Send + Sync {
     fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables;
-    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> Option<Vector4<f32>>;
+    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput;
+
+    // Checked against Storage before each draw so a missing slot fails the draw instead of
+    // panicking mid-frame. Slots read conditionally, like MaterialShader's texture, are left out.
+    fn required_bindings(&self) -> Bindings {
+        Bindings::default()
+    }
+}
+
+// Lets trait objects be passed wherever a shader is expected
+impl<S: Shader + ?Sized> Shader for &S {
+    fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+        (**self).vertex(input_vars)
+    }
+
+    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        (**self).fragment(input_vars)
+    }
+
+    fn required_bindings(&self) -> Bindings {
+        (**self).required_bindings()
+    }
 }
 
+// Discard drops the fragment entirely, it writes neither colour, depth nor stencil.
+// A colour is always kept by the stencil, even with zero alpha, but only blended in when visible.
+pub enum FragmentOutput {
+    Color(Vector4<f32>),
+    // The colour followed by values for RasterOptions::render_targets, which only opaque
+    // fragments write. Values past the number of targets are dropped, empty is a discard.
+    Targets(Vec<Vector4<f32>>),
+    Discard,
+}
+
+const UV: Varying<Vector2<f32>> = Varying::new(0);
+const DIRECTION: Varying<Vector3<f32>> = Varying::new(0);
+const NORMAL: Varying<Vector3<f32>> = Varying::new(0);
+const TANGENT: Varying<Vector4<f32>> = Varying::new(0);
 
 pub struct BasicShader;
 
@@ -16,20 +54,247 @@ impl Shader for BasicShader {
 
         let position = view_projection * transform * input_vars.position;
 
-        VertexShaderOutputVariables {
-            position,
-            vec2: vec![input_vars.texture_coords.xy()],
-            ..Default::default()
-        }
+        VertexShaderOutputVariables::new(position)
+            .with(UV, input_vars.texture_coords.xy())
     }
 
-    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> Option<Vector4<f32>> {
-        let uvs = input_vars.get_input_vec2(0);
+    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        let Some(uvs) = input_vars.get(UV) else { return FragmentOutput::Discard };
 
         let texture = input_vars.storage.get_texture2d(0);
         let base_colour = texture.sample(uvs.x, uvs.y);
 
-        Some(base_colour)
+        FragmentOutput::Color(base_colour)
+    }
+
+    fn required_bindings(&self) -> Bindings {
+        Bindings::default().with_mat4s(&[0, 1]).with_texture2ds(&[0])
+    }
+}
+
+// BasicShader's layout, shading with the bound material's base colour and its texture if it has one
+pub struct MaterialShader;
+
+impl Shader for MaterialShader {
+    fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+        BasicShader.vertex(input_vars)
+    }
+
+    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        let material = input_vars.storage.get_material();
+        let base_colour = material.base_colour.push(material.opacity);
+
+        if material.texture.is_none() {
+            return FragmentOutput::Color(base_colour);
+        }
+
+        let Some(uvs) = input_vars.get(UV) else { return FragmentOutput::Discard };
+        let texture_colour = input_vars.storage.get_texture2d(0).sample(uvs.x, uvs.y);
+
+        FragmentOutput::Color(base_colour.component_mul(&texture_colour))
+    }
+
+    fn required_bindings(&self) -> Bindings {
+        Bindings::default().with_mat4s(&[0, 1])
+    }
+}
+
+// BasicShader's layout with two texture slots, the diffuse texture in slot 0 multiplied by a mask in slot 1
+pub struct MaskedShader;
+
+impl Shader for MaskedShader {
+    fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+        BasicShader.vertex(input_vars)
+    }
+
+    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        let Some(uvs) = input_vars.get(UV) else { return FragmentOutput::Discard };
+
+        let diffuse = input_vars.sample_texture(0, uvs);
+        let mask = input_vars.sample_texture(1, uvs);
+
+        FragmentOutput::Color(diffuse.component_mul(&mask))
+    }
+
+    fn required_bindings(&self) -> Bindings {
+        Bindings::default().with_mat4s(&[0, 1]).with_texture2ds(&[0, 1])
+    }
+}
+
+// BasicShader's layout with a tangent space normal map in texture slot 1, lit by a single
+// directional light. Needs tangents, see Mesh::compute_tangents. light_direction points
+// towards the light, in the space the transform in mat4 1 maps to.
+pub struct NormalMappedShader {
+    pub light_direction: Vector3<f32>,
+    // Light reaching faces turned away from the light, 0 leaves them black
+    pub ambient: f32,
+}
+
+impl Shader for NormalMappedShader {
+    fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+        let transform = input_vars.storage.get_mat4(1);
+
+        // As Mesh::transform, normals use the inverse transpose and tangents the linear part
+        let linear = transform.fixed_view::<3, 3>(0, 0).into_owned();
+        let normal_matrix = linear.try_inverse().map(|inverse| inverse.transpose()).unwrap_or(linear);
+        let normal = normal_matrix * input_vars.normal;
+        let tangent = (linear * input_vars.tangent.xyz()).push(input_vars.tangent.w);
+
+        BasicShader.vertex(input_vars)
+            .with(NORMAL, normal)
+            .with(TANGENT, tangent)
+    }
+
+    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        let (Some(uvs), Some(normal), Some(tangent)) = (input_vars.get(UV), input_vars.get(NORMAL), input_vars.get(TANGENT)) else {
+            return FragmentOutput::Discard
+        };
+
+        // Interpolation denormalizes both and skews them apart, so rebuild an orthonormal basis
+        let handedness = tangent.w.signum();
+        let normal = normal.try_normalize(f32::EPSILON).unwrap_or(Vector3::z());
+        let tangent = (tangent.xyz() - normal * normal.dot(&tangent.xyz())).try_normalize(f32::EPSILON).unwrap_or(Vector3::x());
+        let bitangent = normal.cross(&tangent) * handedness;
+
+        let mapped = input_vars.sample_texture(1, uvs).xyz().map(|channel| channel * 2.0 - 1.0);
+        let surface_normal = (tangent * mapped.x + bitangent * mapped.y + normal * mapped.z).try_normalize(f32::EPSILON).unwrap_or(normal);
+
+        let light_direction = self.light_direction.try_normalize(f32::EPSILON).unwrap_or(Vector3::zeros());
+        let light = (self.ambient + surface_normal.dot(&light_direction).max(0.0)).min(1.0);
+
+        let base_colour = input_vars.sample_texture(0, uvs);
+        FragmentOutput::Color((base_colour.xyz() * light).push(base_colour.w))
+    }
+
+    fn required_bindings(&self) -> Bindings {
+        Bindings::default().with_mat4s(&[0, 1]).with_texture2ds(&[0, 1])
+    }
+}
+
+// BasicShader's layout writing a G-buffer for a deferred lighting pass, the textured colour
+// as usual and the normal from the transform in mat4 1 packed into 0.0 to 1.0 as render target 0
+pub struct GBufferShader;
+
+impl Shader for GBufferShader {
+    fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+        let transform = input_vars.storage.get_mat4(1);
+        let linear = transform.fixed_view::<3, 3>(0, 0).into_owned();
+        let normal_matrix = linear.try_inverse().map(|inverse| inverse.transpose()).unwrap_or(linear);
+        let normal = normal_matrix * input_vars.normal;
+
+        BasicShader.vertex(input_vars)
+            .with(NORMAL, normal)
+    }
+
+    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        let (Some(uvs), Some(normal)) = (input_vars.get(UV), input_vars.get(NORMAL)) else { return FragmentOutput::Discard };
+
+        let albedo = input_vars.sample_texture(0, uvs);
+        let normal = normal.try_normalize(f32::EPSILON).unwrap_or(Vector3::zeros());
+
+        FragmentOutput::Targets(vec![albedo, (normal * 0.5).add_scalar(0.5).push(1.0)])
+    }
+
+    fn required_bindings(&self) -> Bindings {
+        BasicShader.required_bindings()
+    }
+}
+
+// BasicShader's layout with the bone matrices stored from mat4 2 on, the position is skinned
+// in model space before the transform
+pub struct SkinnedShader;
+
+impl Shader for SkinnedShader {
+    fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+        let view_projection = input_vars.storage.get_mat4(0);
+        let transform = input_vars.storage.get_mat4(1);
+
+        let position = view_projection * transform * input_vars.skin_matrix(2) * input_vars.position;
+
+        VertexShaderOutputVariables::new(position)
+            .with(UV, input_vars.texture_coords.xy())
+    }
+
+    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        BasicShader.fragment(input_vars)
+    }
+
+    // Bone matrices depend on the vertices' indices so only the fixed slots are checked
+    fn required_bindings(&self) -> Bindings {
+        BasicShader.required_bindings()
+    }
+}
+
+pub struct InstancedShader;
+
+impl Shader for InstancedShader {
+    fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+        let view_projection = input_vars.storage.get_mat4(0);
+
+        let position = view_projection * input_vars.instance_transform * input_vars.position;
+
+        VertexShaderOutputVariables::new(position)
+            .with(UV, input_vars.texture_coords.xy())
+    }
+
+    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        let Some(uvs) = input_vars.get(UV) else { return FragmentOutput::Discard };
+
+        let texture = input_vars.storage.get_texture2d(0);
+
+        FragmentOutput::Color(texture.sample(uvs.x, uvs.y))
+    }
+
+    fn required_bindings(&self) -> Bindings {
+        Bindings::default().with_mat4s(&[0]).with_texture2ds(&[0])
+    }
+}
+
+// InstancedShader's layout, fading the texture's alpha out where the particle gets within
+// fade_distance depth units of the scene behind it so it doesn't cut a hard line through it
+pub struct SoftParticleShader {
+    pub fade_distance: f32,
+}
+
+impl Shader for SoftParticleShader {
+    fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+        InstancedShader.vertex(input_vars)
+    }
+
+    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        let Some(uvs) = input_vars.get(UV) else { return FragmentOutput::Discard };
+
+        let mut colour = input_vars.sample_texture(0, uvs);
+        colour.w *= (input_vars.scene_depth_gap() / self.fade_distance.max(f32::EPSILON)).clamp(0.0, 1.0);
+
+        FragmentOutput::Color(colour)
+    }
+
+    fn required_bindings(&self) -> Bindings {
+        InstancedShader.required_bindings()
+    }
+}
+
+// Draws a cube around the origin textured by cube map 0. Mat4 0 should be the
+// view projection with the camera's translation removed so the sky stays at infinity.
+pub struct SkyboxShader;
+
+impl Shader for SkyboxShader {
+    fn vertex(&self, input_vars: VertexShaderInputVariables) -> VertexShaderOutputVariables {
+        let view_projection = input_vars.storage.get_mat4(0);
+
+        VertexShaderOutputVariables::new(view_projection * input_vars.position)
+            .with(DIRECTION, input_vars.position.xyz())
+    }
+
+    fn fragment(&self, input_vars: FragmentShaderInputVariables) -> FragmentOutput {
+        let Some(direction) = input_vars.get(DIRECTION) else { return FragmentOutput::Discard };
+
+        FragmentOutput::Color(input_vars.storage.get_cube_map(0).sample(direction))
+    }
+
+    fn required_bindings(&self) -> Bindings {
+        Bindings::default().with_mat4s(&[0]).with_cube_maps(&[0])
     }
 }
 
@@ -37,56 +302,347 @@ pub struct VertexShaderInputVariables<'a> {
     pub position: Vector4<f32>,
     pub texture_coords: Vector3<f32>,
     pub normal: Vector3<f32>,
+    pub tangent: Vector4<f32>,
+    pub bone_indices: [u32; 4],
+    pub bone_weights: Vector4<f32>,
+
+    // Identity and 0 outside of instanced draws
+    pub instance_id: usize,
+    pub instance_transform: Matrix4<f32>,
 
     pub storage: &'a Storage,
 }
 
-#[derive(Default)]
+impl VertexShaderInputVariables<'_> {
+    // The vertex's bone matrices blended by its weights, with bone i stored at mat4 first_bone + i.
+    // Identity when the vertex has no weights so unskinned vertices pass through unchanged.
+    pub fn skin_matrix(&self, first_bone: usize) -> Matrix4<f32> {
+        if self.bone_weights == Vector4::zeros() {
+            return Matrix4::identity();
+        }
+
+        self.bone_indices.iter()
+            .zip(self.bone_weights.iter())
+            .filter(|(_, weight)| **weight != 0.0)
+            .map(|(bone, weight)| self.storage.get_mat4(first_bone + *bone as usize) * *weight)
+            .sum()
+    }
+}
+
+#[derive(Default, Clone)]
 pub struct VertexShaderOutputVariables {
     pub position: Vector4<f32>,
 
     pub vec2: Vec<Vector2<f32>>,
     pub vec3: Vec<Vector3<f32>>,
     pub vec4: Vec<Vector4<f32>>,
+
+    // Flat varyings, never interpolated, every fragment reads the first vertex's value
+    pub flat_vec2: Vec<Vector2<f32>>,
+    pub flat_vec3: Vec<Vector3<f32>>,
+    pub flat_vec4: Vec<Vector4<f32>>,
+}
+
+impl VertexShaderOutputVariables {
+    pub fn new(position: Vector4<f32>) -> Self {
+        Self {
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn with<T: VaryingValue>(mut self, varying: Varying<T>, value: T) -> Self {
+        self.set(varying, value);
+        self
+    }
+
+    // Slots below the written one that haven't been set are filled with zeros
+    pub fn set<T: VaryingValue>(&mut self, varying: Varying<T>, value: T) {
+        let slots = T::slots_mut(self, varying.flat);
+        if slots.len() <= varying.index {
+            slots.resize(varying.index + 1, T::zero());
+        }
+        slots[varying.index] = value;
+    }
+
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: self.position.lerp(&other.position, t),
+            vec2: self.vec2.iter().zip(&other.vec2).map(|(a, b)| a.lerp(b, t)).collect(),
+            vec3: self.vec3.iter().zip(&other.vec3).map(|(a, b)| a.lerp(b, t)).collect(),
+            vec4: self.vec4.iter().zip(&other.vec4).map(|(a, b)| a.lerp(b, t)).collect(),
+            flat_vec2: self.flat_vec2.clone(),
+            flat_vec3: self.flat_vec3.clone(),
+            flat_vec4: self.flat_vec4.clone(),
+        }
+    }
+
+    pub fn copy_flat_from(&mut self, other: &Self) {
+        self.flat_vec2.clone_from(&other.flat_vec2);
+        self.flat_vec3.clone_from(&other.flat_vec3);
+        self.flat_vec4.clone_from(&other.flat_vec4);
+    }
+}
+
+// A typed handle to a varying slot, shared as a constant between the vertex and fragment stages
+// so both agree on where a value lives, e.g. `const UV: Varying<Vector2<f32>> = Varying::new(0);`
+pub struct Varying<T> {
+    index: usize,
+    flat: bool,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Varying<T> {
+    pub const fn new(index: usize) -> Self {
+        Self {
+            index,
+            flat: false,
+            _marker: PhantomData,
+        }
+    }
+
+    // Not interpolated, fragments get the first vertex's value, for ids and flat shaded normals.
+    // Flat slots are separate from interpolated ones, so flat(0) and new(0) don't overlap.
+    pub const fn flat(index: usize) -> Self {
+        Self {
+            index,
+            flat: true,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for Varying<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Varying<T> {}
+
+pub trait VaryingValue: Copy + Add<Output = Self> + Mul<f32, Output = Self> {
+    fn zero() -> Self;
+    fn slots(outputs: &VertexShaderOutputVariables, flat: bool) -> &[Self];
+    fn slots_mut(outputs: &mut VertexShaderOutputVariables, flat: bool) -> &mut Vec<Self>;
+}
+
+impl VaryingValue for Vector2<f32> {
+    fn zero() -> Self {
+        Self::zeros()
+    }
+
+    fn slots(outputs: &VertexShaderOutputVariables, flat: bool) -> &[Self] {
+        if flat { &outputs.flat_vec2 } else { &outputs.vec2 }
+    }
+
+    fn slots_mut(outputs: &mut VertexShaderOutputVariables, flat: bool) -> &mut Vec<Self> {
+        if flat { &mut outputs.flat_vec2 } else { &mut outputs.vec2 }
+    }
+}
+
+impl VaryingValue for Vector3<f32> {
+    fn zero() -> Self {
+        Self::zeros()
+    }
+
+    fn slots(outputs: &VertexShaderOutputVariables, flat: bool) -> &[Self] {
+        if flat { &outputs.flat_vec3 } else { &outputs.vec3 }
+    }
+
+    fn slots_mut(outputs: &mut VertexShaderOutputVariables, flat: bool) -> &mut Vec<Self> {
+        if flat { &mut outputs.flat_vec3 } else { &mut outputs.vec3 }
+    }
+}
+
+impl VaryingValue for Vector4<f32> {
+    fn zero() -> Self {
+        Self::zeros()
+    }
+
+    fn slots(outputs: &VertexShaderOutputVariables, flat: bool) -> &[Self] {
+        if flat { &outputs.flat_vec4 } else { &outputs.vec4 }
+    }
+
+    fn slots_mut(outputs: &mut VertexShaderOutputVariables, flat: bool) -> &mut Vec<Self> {
+        if flat { &mut outputs.flat_vec4 } else { &mut outputs.vec4 }
+    }
 }
 
 pub struct FragmentShaderInputVariables<'a> {
-    vertex_shader_output_variables: &'a [VertexShaderOutputVariables; 3],
+    vertex_shader_output_variables: &'a [&'a VertexShaderOutputVariables; 3],
     bary_coords: Vector3<f32>,
+    // Change in bary_coords per pixel step in screen x and y, zero for lines and points
+    bary_derivatives: [Vector3<f32>; 2],
+    frag_depth: f32,
+    scene_depth: f32,
+    depth_mode: DepthMode,
 
     pub storage: &'a Storage,
 }
 
 impl<'a> FragmentShaderInputVariables<'a> {
-    pub fn new(vertex_shader_output_variables: &'a [VertexShaderOutputVariables; 3], bary_coords: Vector3<f32>, storage: &'a Storage,) -> Self {
+    pub fn new(vertex_shader_output_variables: &'a [&'a VertexShaderOutputVariables; 3], bary_coords: Vector3<f32>, storage: &'a Storage,) -> Self {
         Self {
             vertex_shader_output_variables,
             bary_coords,
+            bary_derivatives: [Vector3::zeros(); 2],
+            frag_depth: 0.0,
+            scene_depth: DepthMode::Standard.clear_depth(),
+            depth_mode: DepthMode::Standard,
             storage,
         }
     }
 
+    pub fn with_depths(mut self, frag_depth: f32, scene_depth: f32, depth_mode: DepthMode) -> Self {
+        self.frag_depth = frag_depth;
+        self.scene_depth = scene_depth;
+        self.depth_mode = depth_mode;
+        self
+    }
+
+    // This fragment's clip space z, the value the depth test compares
+    pub fn frag_depth(&self) -> f32 {
+        self.frag_depth
+    }
+
+    // The opaque depth already at this pixel, the depth mode's clear depth where nothing is.
+    // Only geometry drawn earlier in the frame is there, so draw soft particles after the scene.
+    pub fn scene_depth(&self) -> f32 {
+        self.scene_depth
+    }
+
+    // How far behind this fragment the scene is in depth units, whatever the depth mode,
+    // f32::MAX when nothing opaque has been drawn at the pixel
+    pub fn scene_depth_gap(&self) -> f32 {
+        if self.scene_depth == self.depth_mode.clear_depth() { return f32::MAX }
+
+        self.depth_mode.distance_key(self.scene_depth) - self.depth_mode.distance_key(self.frag_depth)
+    }
+
+    pub fn with_derivatives(mut self, bary_derivatives: [Vector3<f32>; 2]) -> Self {
+        self.bary_derivatives = bary_derivatives;
+        self
+    }
+
+    // Samples the texture bound to the slot, see Storage::set_texture2d_indices
+    pub fn sample_texture(&self, slot: usize, uv: Vector2<f32>) -> Vector4<f32> {
+        self.storage.get_texture2d(slot).sample(uv.x, uv.y)
+    }
+
     pub fn get_position(&self) -> Vector4<f32> {
         self.vertex_shader_output_variables[0].position * self.bary_coords.x +
         self.vertex_shader_output_variables[1].position * self.bary_coords.y +
         self.vertex_shader_output_variables[2].position * self.bary_coords.z
     }
 
-    pub fn get_input_vec2(&self, index: usize) -> Vector2<f32> {
-        self.vertex_shader_output_variables[0].vec2[index] * self.bary_coords.x +
-        self.vertex_shader_output_variables[1].vec2[index] * self.bary_coords.y +
-        self.vertex_shader_output_variables[2].vec2[index] * self.bary_coords.z
+    // None when the vertex stage didn't write the varying
+    pub fn get<T: VaryingValue>(&self, varying: Varying<T>) -> Option<T> {
+        self.interpolate(varying, self.bary_coords)
+    }
+
+    // How much the varying changes per pixel in screen x and y, for picking mip levels.
+    // Zero for flat varyings.
+    pub fn get_derivatives<T: VaryingValue>(&self, varying: Varying<T>) -> Option<(T, T)> {
+        if varying.flat {
+            return self.get(varying).map(|_| (T::zero(), T::zero()));
+        }
+
+        let [ddx, ddy] = self.bary_derivatives;
+        Some((self.interpolate(varying, ddx)?, self.interpolate(varying, ddy)?))
+    }
+
+    fn interpolate<T: VaryingValue>(&self, varying: Varying<T>, weights: Vector3<f32>) -> Option<T> {
+        let [a, b, c] = self.vertex_shader_output_variables.map(|outputs| T::slots(outputs, varying.flat).get(varying.index).copied());
+
+        if varying.flat { return a }
+        Some(a? * weights.x + b? * weights.y + c? * weights.z)
+    }
+
+    pub fn get_input_vec2(&self, index: usize) -> Option<Vector2<f32>> {
+        self.get(Varying::new(index))
+    }
+
+    pub fn get_input_vec3(&self, index: usize) -> Option<Vector3<f32>> {
+        self.get(Varying::new(index))
     }
 
-    pub fn get_input_vec3(&self, index: usize) -> Vector3<f32> {
-        self.vertex_shader_output_variables[0].vec3[index] * self.bary_coords.x +
-        self.vertex_shader_output_variables[1].vec3[index] * self.bary_coords.y +
-        self.vertex_shader_output_variables[2].vec3[index] * self.bary_coords.z
+    pub fn get_input_vec4(&self, index: usize) -> Option<Vector4<f32>> {
+        self.get(Varying::new(index))
     }
+}
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix4, Translation3, Vector3, Vector4};
+    use crate::mesh::Mesh;
+    use crate::renderer::rasterizer::storage::Storage;
+    use crate::renderer::rasterizer::texture2d::Texture2D;
+    use crate::renderer::rasterizer::{RasterOptions, Rasterizer};
+    use super::{GBufferShader, NormalMappedShader, Shader, SkinnedShader, VertexShaderInputVariables};
+
+    #[test]
+    fn two_bones_blend_by_weight() {
+        let mut storage = Storage::default();
+        storage.set_mat4s(vec![
+            Matrix4::identity(),
+            Matrix4::identity(),
+            Translation3::new(2.0, 0.0, 0.0).to_homogeneous(),
+            Translation3::new(0.0, 4.0, 0.0).to_homogeneous(),
+        ]);
+
+        let input_vars = VertexShaderInputVariables {
+            position: Vector4::new(0.0, 0.0, 0.0, 1.0),
+            texture_coords: Vector3::zeros(),
+            normal: Vector3::z(),
+            tangent: Vector4::new(1.0, 0.0, 0.0, 1.0),
+            bone_indices: [0, 1, 0, 0],
+            bone_weights: Vector4::new(0.75, 0.25, 0.0, 0.0),
+            instance_id: 0,
+            instance_transform: Matrix4::identity(),
+            storage: &storage,
+        };
+
+        let position = SkinnedShader.vertex(input_vars).position;
+        assert_eq!(position, Vector4::new(1.5, 1.0, 0.0, 1.0));
+    }
+
+    // A full screen quad facing the camera lit from the side, so only a normal map tilting
+    // its surface towards +x lets the light reach it
+    fn lit_by_normal_map(normal_texel: [u8; 4]) -> Vector3<f32> {
+        let mut rasterizer = Rasterizer::new(4, 4, RasterOptions::default());
+        rasterizer.storage_mut().set_mat4s(vec![Matrix4::identity(), Matrix4::identity()]);
+        rasterizer.storage_mut().set_texture2ds(vec![
+            Texture2D::from_rgba(1, 1, vec![255; 4]).unwrap(),
+            Texture2D::from_rgba(1, 1, normal_texel.to_vec()).unwrap(),
+        ]);
+        rasterizer.storage_mut().set_texture2d_indices(&[0, 1]);
+
+        let shader = NormalMappedShader { light_direction: Vector3::x(), ambient: 0.0 };
+        rasterizer.draw_mesh(&Mesh::quad_spanning(Vector3::zeros(), Vector3::x() * 2.0, Vector3::y() * 2.0), &shader).unwrap();
+        rasterizer.pixel_at(2, 2).unwrap()
+    }
+
+    #[test]
+    fn normal_map_bends_the_surface_normal() {
+        // 128 decodes to just over 0, leaving a sliver of light
+        let flat = lit_by_normal_map([128, 128, 255, 255]);
+        assert!(flat.iter().all(|&channel| channel < 0.01), "{flat:?}");
 
-    pub fn get_input_vec4(&self, index: usize) -> Vector4<f32> {
-        self.vertex_shader_output_variables[0].vec4[index] * self.bary_coords.x +
-        self.vertex_shader_output_variables[1].vec4[index] * self.bary_coords.y +
-        self.vertex_shader_output_variables[2].vec4[index] * self.bary_coords.z
+        let tilted = lit_by_normal_map([255, 128, 128, 255]);
+        assert!(tilted.iter().all(|&channel| channel > 0.99), "{tilted:?}");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn g_buffer_holds_albedo_and_normal() {
+        let mut rasterizer = Rasterizer::new(4, 4, RasterOptions { render_targets: 1, ..Default::default() });
+        rasterizer.storage_mut().set_mat4s(vec![Matrix4::identity(), Matrix4::identity()]);
+        rasterizer.storage_mut().set_texture2ds(vec![Texture2D::from_rgba(1, 1, vec![255, 0, 0, 255]).unwrap()]);
+        rasterizer.storage_mut().set_texture2d_indices(&[0]);
+
+        // Facing +z, towards the camera
+        rasterizer.draw_mesh(&Mesh::quad_spanning(Vector3::zeros(), Vector3::x() * 2.0, Vector3::y() * 2.0), &GBufferShader).unwrap();
+
+        // Albedo is the colour output, the normal packed into 0 to 1 the first target
+        assert_eq!(rasterizer.pixel_at(2, 2), Some(Vector3::x()));
+        assert_eq!(rasterizer.render_target(0)[2 * 4 + 2], Vector4::new(0.5, 0.5, 1.0, 1.0));
+    }
+}
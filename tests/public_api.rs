@@ -0,0 +1,85 @@
+// Everything the demo reaches through simple_raster, so moving or hiding any of it fails here
+// rather than in the binary
+use std::io::BufReader;
+use nalgebra::{Matrix4, Point3, Rotation3, Translation3, Vector3};
+use simple_raster::camera::PerspectiveCamera;
+use simple_raster::mesh::{Mesh, ObjLoader};
+use simple_raster::renderer::post_processor::PostProcessorOptions;
+use simple_raster::renderer::rasterizer::background::Background;
+use simple_raster::renderer::rasterizer::depth::DepthMode;
+use simple_raster::renderer::rasterizer::texture2d::Texture2D;
+use simple_raster::renderer::rasterizer::{PolygonMode, RasterOptions, TransparencyMode};
+use simple_raster::renderer::scene::{DrawItem, Scene, Transparency};
+use simple_raster::renderer::{Renderer, RendererOptions};
+use simple_raster::shader::BasicShader;
+
+const TRIANGLE_OBJ: &str = "v -0.5 -0.5 0\nv 0.5 -0.5 0\nv 0 0.5 0\nvt 0 0\nvt 1 0\nvt 0.5 1\nf 1/1 2/2 3/3\n";
+
+#[test]
+fn demo_surface_renders_a_scene() {
+    let (width, height) = (64, 36);
+
+    let meshes = ObjLoader::new().parse(BufReader::new(TRIANGLE_OBJ.as_bytes()));
+    let mesh = meshes[0].to_indexed();
+    let window = Mesh::quad();
+
+    assert!(Texture2D::load("missing_texture.png").is_err());
+    let texture = Texture2D::from_rgba(1, 1, vec![255, 255, 255, 255]).unwrap();
+    let window_texture = Texture2D::from_rgba(1, 1, vec![0, 0, 255, 128]).unwrap();
+
+    let options = RendererOptions {
+        raster_options: RasterOptions {
+            cull_backfaces: false,
+            polygon_mode: PolygonMode::Fill,
+            background: Background::Solid(Vector3::new(0.529, 0.808, 0.980)),
+            stencil: None,
+            alpha_cutoff: None,
+            transparency: TransparencyMode::Sorted,
+            fog: None,
+            depth_mode: DepthMode::Standard,
+            ..Default::default()
+        },
+        post_processor_options: PostProcessorOptions {
+            fxaa: true,
+            sharpen: None,
+            color_filter: None,
+            chromatic_aberration: None,
+            outline: None,
+            debug_depth: false,
+        },
+        render_scale: 1.0,
+        supersample: 1,
+    };
+    let mut renderer = Renderer::new(width, height, options);
+    renderer.resize(width, height);
+
+    let fovy = 60.0_f32.to_radians();
+    let mut camera = PerspectiveCamera::new(Point3::new(0.0, 0.0, 4.0), Vector3::zeros(), fovy, width as f32 / height as f32, 0.1, 100.0);
+    camera.projection = PerspectiveCamera::perspective_projection(fovy, width as f32 / height as f32, 0.1, 100.0);
+    camera.update_view();
+
+    let shader = BasicShader;
+    let window_transform = Translation3::from(Vector3::new(0.5, 0.5, 1.0)).to_homogeneous();
+    let model_transform = Rotation3::from_axis_angle(&Vector3::y_axis(), 0.0).to_homogeneous();
+
+    let storage = renderer.rasterizer.storage_mut();
+    storage.set_texture2ds(vec![texture, window_texture]);
+    storage.set_mat4s(vec![camera.view_projection, window_transform]);
+    storage.set_texture2d_indices(&[1]);
+    renderer.rasterizer.draw_mesh(&window, &shader).unwrap();
+    renderer.rasterizer.storage_mut().set_mat4(1, Matrix4::identity());
+    renderer.rasterizer.storage_mut().set_texture2d_index(0, 0);
+    renderer.rasterizer.draw_indexed_mesh(&mesh, &shader).unwrap();
+
+    let mut buffer = vec![0; width * height];
+    renderer.render(&mut buffer);
+
+    let mut scene = Scene::new(camera.view_projection);
+    scene.sort = true;
+    scene.add(DrawItem::new(&window, window_transform, vec![1], &shader).with_transparency(Transparency::Blend));
+    scene.add(DrawItem::new(&mesh, model_transform, vec![0], &shader));
+    renderer.render_scene(&scene, &mut buffer).unwrap();
+
+    // The white triangle faces the camera at the centre of the frame, below the window
+    assert_eq!(buffer[(height / 2 + 2) * width + width / 2], 0xffffff);
+}